@@ -1,4 +1,9 @@
+use std::any::Any;
 use nalgebra::{DVector};
+
+/// Opaque copy of an env's full state (including hidden information), see `Env::snapshot`
+pub type Snapshot = Box<dyn Any>;
+
 #[allow(dead_code)]
 pub trait Env {
     fn num_states(&self) -> usize;
@@ -6,6 +11,10 @@ pub trait Env {
     fn num_rewards(&self) -> usize;
     fn get_reward_vector(&self) -> Vec<f32>;
     fn get_terminal_states(&self) -> Vec<usize>;
+    /// States the agent can never stand in, e.g. the walls of a grid
+    fn get_blocked_states(&self) -> Vec<usize> {
+        Vec::new()
+    }
     fn get_reward(&self, num: usize) -> f32;
     fn get_action_spaces(&self) -> Vec<usize>;
     fn p(&self, s: i32, a: i32, s_p: i32, r_index: i32) -> f32;
@@ -19,4 +28,21 @@ pub trait Env {
     fn score(&self) -> f32;
     fn from_random_state() -> Self where Self: Sized;
     fn transition_probability(&self, s: usize, a: usize, s_p: usize, r_index: usize) -> f32;
+    /// Put the env in `state_id`, drawing any hidden information at random
+    fn set_state(&mut self, state_id: usize);
+    /// Copy of the current state, to come back to it later with `restore`
+    fn snapshot(&self) -> Snapshot;
+    fn restore(&mut self, snapshot: &Snapshot);
+    /// Draw the hidden information again from its posterior given what the agent
+    /// has observed, so that a planner working on a copy cannot peek at it.
+    /// Nothing to do when the env has no hidden information.
+    fn resample_hidden(&mut self) {}
+}
+
+/// `Env::restore` for the envs whose snapshot is a clone of themselves
+pub fn restore_clone<E: Clone + 'static>(env: &mut E, snapshot: &Snapshot) {
+    match snapshot.downcast_ref::<E>() {
+        Some(saved) => env.clone_from(saved),
+        None => panic!("Snapshot was taken on another env"),
+    }
 }
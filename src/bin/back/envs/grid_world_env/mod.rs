@@ -1,13 +1,22 @@
 use nalgebra::DVector;
+use rand::Rng;
 
-use crate::back::envs::basic_env::Env;
+use crate::back::envs::basic_env::{restore_clone, Env, Snapshot};
 
+#[derive(Clone)]
 pub struct GridEnv {
     s: DVector<i32>,
     a: DVector<i32>,
     r: DVector<i32>,
     p: Vec<Vec<Vec<Vec<f32>>>>,
     t: Vec<usize>,
+    /// Reward for entering each terminal state of `t`
+    terminal_rewards: Vec<f32>,
+    walls: Vec<usize>,
+    start: usize,
+    /// New walls and the number of steps (over all episodes) after which they replace the current ones
+    layout_change: Option<(usize, Vec<usize>)>,
+    total_steps: usize,
     pub rows: usize,
     pub cols: usize,
     current_state: usize,
@@ -16,69 +25,154 @@ pub struct GridEnv {
 
 impl GridEnv {
     pub fn new() -> Self {
-        let rows = 3;
-        let cols = 3;
-        let s = DVector::from_vec(vec![0; rows * cols]);
-        let a = DVector::from_vec(vec![0, 1, 2, 3]);
-        let r = DVector::from_vec(vec![-1, 0, 1]);
-        let t = vec![0, 8];
-        let mut p = vec![vec![vec![vec![0.0; r.len()]; s.len()]; a.len()]; s.len()];
-
-        for state in 0..s.len() {
-            let (row, col) = (state / cols, state % cols);
-
-            for action in 0..a.len() {
-                // Determine the next state based on the action
-                let (new_row, new_col) = match action {
-                    0 if row > 0 => (row - 1, col),        // Up
-                    1 if row < rows - 1 => (row + 1, col), // Down
-                    2 if col > 0 => (row, col - 1),        // Left
-                    3 if col < cols - 1 => (row, col + 1), // Right
-                    _ => (row, col),                       // Stay in the current state (out of bounds)
-                };
-
-                let new_state = new_row * cols + new_col;
-
-                // Handle terminal states
-                if state == 0 {
-                    // Top-left: Terminal state with reward -1.0
-                    p[state][action][state][0] = 1.0; // Reward index 0 corresponds to -1
-                } else if state == s.len() - 1 {
-                    // Bottom-right: Terminal state with reward +1.0
-                    p[state][action][state][2] = 1.0; // Reward index 2 corresponds to +1
-                } else {
-                    // Non-terminal states
-                    p[state][action][new_state][1] = 1.0; // Reward index 1 corresponds to 0
-                }
-            }
-        }
+        // For simplicity, start at the middle cell
+        let env = Self::with_layout(3, 3, 4, vec![(0, -1.0), (8, 1.0)], vec![]);
 
         // Debug: Print the transition probabilities for verification
-        for state in 0..s.len() {
-            for action in 0..a.len() {
+        for state in 0..env.s.len() {
+            for action in 0..env.a.len() {
                 println!(
                     "State: {}, Action: {}, Transition: {:?}",
-                    state, action, &p[state][action]
+                    state, action, &env.p[state][action]
                 );
             }
         }
-        // For simplicity, start at middle cell if possible,
-        // otherwise just start at 0.
-        let grid_size = rows * cols;
-        let starting_state = if grid_size > 0 { grid_size / 2 } else { 0 };
-
-        GridEnv {
-            s,
-            a,
-            r,
-            t,
-            p,
+        env
+    }
+
+    /// Grid of `rows` x `cols` cells where moving into a wall or out of the grid
+    /// leaves the agent in place. `terminals` holds (cell, reward for entering it),
+    /// the rewards being -1, 0 or 1.
+    pub fn with_layout(rows: usize, cols: usize, start: usize, terminals: Vec<(usize, f32)>, walls: Vec<usize>) -> Self {
+        let num_cells = rows * cols;
+        if start >= num_cells || walls.contains(&start) {
+            panic!("Invalid start cell: {}", start);
+        }
+        if terminals.iter().any(|(cell, reward)| *cell >= num_cells || ![-1.0, 0.0, 1.0].contains(reward)) {
+            panic!("Invalid terminal states: {:?}", terminals);
+        }
+        let mut env = GridEnv {
+            s: DVector::from_vec(vec![0; num_cells]),
+            a: DVector::from_vec(vec![0, 1, 2, 3]),
+            r: DVector::from_vec(vec![-1, 0, 1]),
+            p: Vec::new(),
+            t: terminals.iter().map(|(cell, _)| *cell).collect(),
+            terminal_rewards: terminals.iter().map(|(_, reward)| *reward).collect(),
+            walls,
+            start,
+            layout_change: None,
+            total_steps: 0,
             rows,
             cols,
-            current_state: starting_state,
+            current_state: start,
             current_score: 0.0,
+        };
+        env.p = env.build_p();
+        env
+    }
+
+    /// Blocking maze (Sutton & Barto, example 8.2): the short path on the right
+    /// is blocked after `change_after` steps and a longer one opens on the left
+    pub fn blocking_maze(change_after: usize) -> Self {
+        let wall_row = |cols: std::ops::RangeInclusive<usize>| cols.map(|col| 3 * 9 + col).collect::<Vec<usize>>();
+        Self::with_layout(6, 9, 5 * 9 + 3, vec![(8, 1.0)], wall_row(0..=7)).with_layout_change(change_after, wall_row(1..=8))
+    }
+
+    /// Shortcut maze (Sutton & Barto, example 8.3): a shorter path opens on the
+    /// right after `change_after` steps
+    pub fn shortcut_maze(change_after: usize) -> Self {
+        let wall_row = |cols: std::ops::RangeInclusive<usize>| cols.map(|col| 3 * 9 + col).collect::<Vec<usize>>();
+        Self::with_layout(6, 9, 5 * 9 + 3, vec![(8, 1.0)], wall_row(1..=8)).with_layout_change(change_after, wall_row(1..=7))
+    }
+
+    /// Dyna maze (Sutton & Barto, example 8.1), scaled by `factor`: each cell
+    /// becomes a `factor` x `factor` block, the start and the goal staying single cells
+    pub fn dyna_maze(factor: usize) -> Self {
+        let factor = factor.max(1);
+        let (rows, cols) = (6 * factor, 9 * factor);
+        let walls = [(1, 2), (2, 2), (3, 2), (4, 5), (0, 7), (1, 7), (2, 7)]
+            .iter()
+            .flat_map(|&(row, col)| (0..factor * factor).map(move |i| ((row * factor + i / factor) * cols) + col * factor + i % factor))
+            .collect();
+        Self::with_layout(rows, cols, 2 * factor * cols, vec![(cols - 1, 1.0)], walls)
+    }
+
+    /// Length of the shortest path from the start to a terminal state (breadth-first search
+    /// on the current layout), `None` if none can be reached
+    pub fn shortest_path_length(&self) -> Option<usize> {
+        let mut distances = vec![usize::MAX; self.s.len()];
+        let mut queue = std::collections::VecDeque::from([self.start]);
+        distances[self.start] = 0;
+        while let Some(state) = queue.pop_front() {
+            if self.t.contains(&state) {
+                return Some(distances[state]);
+            }
+            for action in 0..self.a.len() {
+                let next = self.next_cell(state, action);
+                if distances[next] == usize::MAX {
+                    distances[next] = distances[state] + 1;
+                    queue.push_back(next);
+                }
+            }
         }
+        None
+    }
 
+    /// Replaces the walls by `walls` once `after_steps` steps have been played,
+    /// counting the steps of every episode since the creation of the env
+    pub fn with_layout_change(mut self, after_steps: usize, walls: Vec<usize>) -> Self {
+        self.layout_change = Some((after_steps, walls));
+        self
+    }
+
+    /// Cell reached by `action` from `state`, staying in place against walls and borders
+    fn next_cell(&self, state: usize, action: usize) -> usize {
+        let (row, col) = self.index_to_rc(state);
+        let (new_row, new_col) = match action {
+            0 if row > 0 => (row - 1, col),             // Up
+            1 if row < self.rows - 1 => (row + 1, col), // Down
+            2 if col > 0 => (row, col - 1),             // Left
+            3 if col < self.cols - 1 => (row, col + 1), // Right
+            _ => (row, col),                            // Stay in the current state (out of bounds)
+        };
+        let new_state = self.rc_to_index(new_row, new_col);
+        if self.walls.contains(&new_state) {
+            state
+        } else {
+            new_state
+        }
+    }
+
+    fn terminal_reward(&self, state: usize) -> Option<f32> {
+        self.t.iter().position(|&terminal| terminal == state).map(|i| self.terminal_rewards[i])
+    }
+
+    fn build_p(&self) -> Vec<Vec<Vec<Vec<f32>>>> {
+        let mut p = vec![vec![vec![vec![0.0; self.r.len()]; self.s.len()]; self.a.len()]; self.s.len()];
+        for (state, p_s) in p.iter_mut().enumerate() {
+            // Terminal states are absorbing and walls are never visited: no transition out of them
+            if self.t.contains(&state) || self.walls.contains(&state) {
+                continue;
+            }
+            for (action, p_s_a) in p_s.iter_mut().enumerate() {
+                let new_state = self.next_cell(state, action);
+                // The reward is given when entering a terminal state, like in `step`
+                let reward = self.terminal_reward(new_state).unwrap_or(0.0);
+                p_s_a[new_state][(reward + 1.0) as usize] = 1.0;
+            }
+        }
+        p
+    }
+
+    /// Applies the pending layout change once its time has come, unless the agent stands on a new wall
+    fn update_layout(&mut self) {
+        if let Some((after_steps, walls)) = &self.layout_change {
+            if self.total_steps >= *after_steps && !walls.contains(&self.current_state) {
+                self.walls = walls.clone();
+                self.layout_change = None;
+                self.p = self.build_p();
+            }
+        }
     }
 
     /// Utility to convert a (row, col) pair into a single flattened index.
@@ -115,6 +209,10 @@ impl Env for GridEnv {
         self.t.clone()
     }
 
+    fn get_blocked_states(&self) -> Vec<usize> {
+        self.walls.clone()
+    }
+
     fn get_reward(&self, _num: usize) -> f32 {
         // You could define a more interesting reward structure.
         self.current_score
@@ -133,9 +231,7 @@ impl Env for GridEnv {
     }
 
     fn reset(&mut self) {
-        // Reset to the center of the grid or 0 if there's no space.
-        let grid_size = self.rows * self.cols;
-        self.current_state = if grid_size > 0 { grid_size / 2 } else { 0 };
+        self.current_state = self.start;
         self.current_score = 0.0;
     }
 
@@ -145,6 +241,8 @@ impl Env for GridEnv {
             for col in 0..self.cols {
                 if row == agent_row && col == agent_col {
                     print!("X ");
+                } else if self.walls.contains(&self.rc_to_index(row, col)) {
+                    print!("# ");
                 } else {
                     print!("_ ");
                 }
@@ -185,44 +283,16 @@ impl Env for GridEnv {
             panic!("Trying to play when the game is over!");
         }
 
-        let (mut row, mut col) = self.index_to_rc(self.current_state);
-
-        match action {
-            0 => { // Up
-                if row > 0 {
-                    row -= 1;
-                }
-            }
-            1 => { // Down
-                if row < self.rows - 1 {
-                    row += 1;
-                }
-            }
-            2 => { // Left
-                if col > 0 {
-                    col -= 1;
-                }
-            }
-            3 => { // Right
-                if col < self.cols - 1 {
-                    col += 1;
-                }
-            }
-            _ => panic!("Invalid action"),
-        };
-
-        self.current_state = self.rc_to_index(row, col);
+        self.current_state = self.next_cell(self.current_state, action as usize);
 
-        // For demonstration, let's say if top-left corner is 0, you lose,
-        // bottom-right corner is a 'win' scenario.
-        // This is purely arbitrary; feel free to replace with your own logic.
-        if self.current_state == 0 {
-            self.current_score = -1.0;
-        } else if self.current_state == (self.rows * self.cols - 1) {
-            self.current_score = 1.0;
-        } else {
-            // Otherwise, 0.0 or accumulate something else as you wish.
+        // Entering a terminal state ends the game with its reward,
+        // e.g. losing in the top-left corner and winning in the bottom-right one of the 3x3 grid
+        if let Some(reward) = self.terminal_reward(self.current_state) {
+            self.current_score = reward;
         }
+
+        self.total_steps += 1;
+        self.update_layout();
     }
 
     fn score(&self) -> f32 {
@@ -233,12 +303,34 @@ impl Env for GridEnv {
     where
         Self: Sized,
     {
-        // You might want to create a random grid or place the agent randomly.
-        // For now, we panic to match the style of the example.
-        panic!("Not yet implemented");
+        // Place the agent on a random non-terminal cell
+        let mut env = GridEnv::new();
+        let mut rng = rand::thread_rng();
+        let mut state = rng.gen_range(0..env.s.len());
+        while env.t.contains(&state) || env.walls.contains(&state) {
+            state = rng.gen_range(0..env.s.len());
+        }
+        env.set_state(state);
+        env
     }
 
     fn transition_probability(&self, s: usize, a: usize, s_p: usize, r_index: usize) -> f32 {
         self.p[s][a][s_p][r_index]
     }
+
+    fn set_state(&mut self, state_id: usize) {
+        if state_id >= self.s.len() || self.walls.contains(&state_id) {
+            panic!("Invalid state: {}", state_id);
+        }
+        self.current_state = state_id;
+        self.current_score = self.terminal_reward(state_id).unwrap_or(0.0);
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Box::new(self.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        restore_clone(self, snapshot);
+    }
 }
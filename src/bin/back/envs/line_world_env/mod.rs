@@ -1,7 +1,9 @@
 use nalgebra::DVector;
+use rand::Rng;
 
-use crate::back::envs::basic_env::Env;
+use crate::back::envs::basic_env::{restore_clone, Env, Snapshot};
 
+#[derive(Clone)]
 pub struct LineEnv {
     s: DVector<i32>,
     a: DVector<i32>,
@@ -14,21 +16,31 @@ pub struct LineEnv {
 
 impl LineEnv {
     pub fn new() -> Self {
-        let s = DVector::from_vec(vec![0, 1, 2, 3, 4]);
+        Self::with_size(5)
+    }
+
+    /// Line of `num_states` cells (terminals included), starting in the middle.
+    /// Reaching the left end gives -1, the right end +1.
+    pub fn with_size(num_states: usize) -> Self {
+        if num_states < 3 {
+            panic!("A line needs at least 3 states, got {}", num_states);
+        }
+        let last = num_states as i32 - 1;
+        let s = DVector::from_vec((0..=last).collect());
         let a = DVector::from_vec(vec![0, 1]);
         let r = DVector::from_vec(vec![-1, 0, 1]);
-        let t = vec![0, 4];
+        let t = vec![0, last as usize];
         let mut p = vec![vec![vec![vec![0.0f32; r.len()]; s.len()]; a.len()]; s.len()];
 
         for &s_p in &s {
-            if s_p == 0 || s_p == 4 {
+            if s_p == 0 || s_p == last {
                 continue;
             }
             for &action in &a {
                 if action == 0 && s_p > 1 {
                     p[s_p as usize][action as usize][(s_p - 1) as usize][1] = 1.0;
                 }
-                if action == 1 && s_p < 3 {
+                if action == 1 && s_p < last - 1 {
                     p[s_p as usize][action as usize][(s_p + 1) as usize][1] = 1.0;
                 }
             }
@@ -36,7 +48,7 @@ impl LineEnv {
 
         // Set probabilities
         p[1][0][0][0] = 1.0;
-        p[3][1][4][2] = 1.0;
+        p[last as usize - 1][1][last as usize][2] = 1.0;
         let current_state = s.len() / 2;
         let current_score = 0.0;
 
@@ -145,11 +157,36 @@ impl Env for LineEnv {
     where
         Self: Sized,
     {
-        panic!("Not yet implemented");
+        let mut env = LineEnv::new();
+        let state = rand::thread_rng().gen_range(1..env.s.len() - 1);
+        env.set_state(state);
+        env
     }
 
     fn transition_probability(&self, state: usize, a: usize, s_p: usize, r_index: usize) -> f32 {
         self.p[state][a][s_p][r_index]
     }
+
+    fn set_state(&mut self, state_id: usize) {
+        if state_id >= self.s.len() {
+            panic!("Invalid state: {}", state_id);
+        }
+        self.current_state = state_id;
+        self.current_score = if state_id == 0 {
+            -1.0
+        } else if state_id == self.s.len() - 1 {
+            1.0
+        } else {
+            0.0
+        };
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Box::new(self.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        restore_clone(self, snapshot);
+    }
 }
 
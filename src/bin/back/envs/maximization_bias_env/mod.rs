@@ -0,0 +1,180 @@
+use nalgebra::DVector;
+use rand::Rng;
+
+use crate::back::envs::basic_env::{restore_clone, Env, Snapshot};
+
+/// State A, where every episode starts
+pub const STATE_A: usize = 0;
+/// State B, reached by going left from A
+pub const STATE_B: usize = 1;
+pub const TERMINAL: usize = 2;
+/// Going left from A leads to B, going right ends the episode
+pub const LEFT: i32 = 0;
+pub const RIGHT: i32 = 1;
+
+/// Maximisation bias MDP (Sutton & Barto, example 6.7).
+/// Every action from B ends the episode with a reward drawn from N(mean, 1):
+/// with a negative mean, going left is worse, but the max over the noisy
+/// estimates of B looks positive early on.
+#[derive(Clone)]
+pub struct MaximizationBiasEnv {
+    b_actions: usize,
+    mean: f32,
+    current_state: usize,
+    current_score: f32,
+}
+
+impl MaximizationBiasEnv {
+    pub fn new() -> Self {
+        Self::with_params(10, -0.1)
+    }
+
+    pub fn with_params(b_actions: usize, mean: f32) -> Self {
+        if b_actions == 0 {
+            panic!("B needs at least one action");
+        }
+        MaximizationBiasEnv { b_actions, mean, current_state: STATE_A, current_score: 0.0 }
+    }
+
+    /// Standard normal sample (Box-Muller)
+    fn standard_normal(rng: &mut impl Rng) -> f32 {
+        let u1: f32 = 1.0 - rng.gen::<f32>();
+        let u2: f32 = rng.gen();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+    }
+}
+
+impl Default for MaximizationBiasEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Env for MaximizationBiasEnv {
+    fn num_states(&self) -> usize {
+        3
+    }
+
+    fn num_actions(&self) -> usize {
+        self.b_actions.max(2)
+    }
+
+    fn num_rewards(&self) -> usize {
+        2
+    }
+
+    /// The model only knows the expected reward of B
+    fn get_reward_vector(&self) -> Vec<f32> {
+        vec![0.0, self.mean]
+    }
+
+    fn get_terminal_states(&self) -> Vec<usize> {
+        vec![TERMINAL]
+    }
+
+    fn get_reward(&self, _num: usize) -> f32 {
+        self.current_score
+    }
+
+    fn get_action_spaces(&self) -> Vec<usize> {
+        vec![2, self.b_actions, 0]
+    }
+
+    fn p(&self, _s: i32, _a: i32, _s_p: i32, _r_index: i32) -> f32 {
+        panic!("Not yet implemented");
+    }
+
+    fn state_id(&self) -> usize {
+        self.current_state
+    }
+
+    fn reset(&mut self) {
+        self.current_state = STATE_A;
+        self.current_score = 0.0;
+    }
+
+    fn display(&self) {
+        let cell = |state: usize, name: &str| if self.current_state == state { "X".to_string() } else { name.to_string() };
+        println!("[T] {} {} [T]", cell(STATE_B, "B"), cell(STATE_A, "A"));
+        if self.current_state == TERMINAL {
+            println!("Episode over");
+        }
+    }
+
+    fn is_forbidden(&self, action: usize) -> bool {
+        !self.available_actions().iter().any(|&a| a as usize == action)
+    }
+
+    fn is_game_over(&self) -> bool {
+        self.current_state == TERMINAL
+    }
+
+    fn available_actions(&self) -> DVector<i32> {
+        match self.current_state {
+            STATE_A => DVector::from_vec(vec![LEFT, RIGHT]),
+            STATE_B => DVector::from_vec((0..self.b_actions as i32).collect()),
+            _ => DVector::zeros(0),
+        }
+    }
+
+    fn step(&mut self, action: i32) {
+        if self.is_game_over() {
+            panic!("Trying to play when game is over!");
+        }
+        if self.is_forbidden(action as usize) {
+            panic!("Invalid action");
+        }
+
+        match (self.current_state, action) {
+            (STATE_A, LEFT) => self.current_state = STATE_B,
+            (STATE_A, _) => self.current_state = TERMINAL,
+            _ => {
+                self.current_score += self.mean + Self::standard_normal(&mut rand::thread_rng());
+                self.current_state = TERMINAL;
+            }
+        }
+    }
+
+    fn score(&self) -> f32 {
+        self.current_score
+    }
+
+    fn from_random_state() -> Self
+    where
+        Self: Sized,
+    {
+        let mut env = MaximizationBiasEnv::new();
+        env.set_state(rand::thread_rng().gen_range(STATE_A..=STATE_B));
+        env
+    }
+
+    fn transition_probability(&self, s: usize, a: usize, s_p: usize, r_index: usize) -> f32 {
+        let (next, reward) = match (s, a as i32) {
+            (STATE_A, LEFT) => (STATE_B, 0),
+            (STATE_A, RIGHT) => (TERMINAL, 0),
+            (STATE_B, a) if (a as usize) < self.b_actions => (TERMINAL, 1),
+            _ => return 0.0,
+        };
+        if s_p == next && r_index == reward {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn set_state(&mut self, state_id: usize) {
+        if state_id >= self.num_states() {
+            panic!("Invalid state: {}", state_id);
+        }
+        self.current_state = state_id;
+        self.current_score = 0.0;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Box::new(self.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        restore_clone(self, snapshot);
+    }
+}
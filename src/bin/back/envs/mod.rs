@@ -4,4 +4,7 @@ pub mod grid_world_env;
 pub mod rock_paper_scissors;
 pub mod monty_hall_1;
 pub mod monty_hall_2;
-pub mod secret_env;
\ No newline at end of file
+pub mod maximization_bias_env;
+pub mod tabular_mdp;
+pub mod secret_env;
+pub mod plugin;
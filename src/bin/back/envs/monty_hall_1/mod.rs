@@ -2,7 +2,12 @@ use rand::Rng;
 use nalgebra::DVector;
 
 pub use crate::back::envs::basic_env::Env;
+use crate::back::envs::basic_env::{restore_clone, Snapshot};
 
+/// Monty Hall with 3 doors. When the agent picked the winning door, the host opens
+/// one of the two others at random. States: 0 before choosing, 1 + 2 * chosen + k
+/// once the host opened the k-th smaller door of the two others, 7 at the end.
+#[derive(Clone)]
 pub struct MontyHallEnv {
     winning_door: usize,
     chosen_door: Option<usize>,
@@ -28,35 +33,44 @@ impl MontyHallEnv {
 
     fn reveal_remaining_door(&mut self) {
         if let Some(chosen) = self.chosen_door {
-            self.remaining_door = Some(
-                (0..3)
-                    .filter(|&door| door != chosen && door != self.winning_door)
-                    .next()
-                    .unwrap(),
-            );
+            let doors: Vec<usize> = (0..3).filter(|&door| door != chosen && door != self.winning_door).collect();
+            self.remaining_door = Some(doors[rand::thread_rng().gen_range(0..doors.len())]);
         }
     }
+
+    /// State after choosing `chosen`, the host having opened `opened`
+    fn choice_state(chosen: usize, opened: usize) -> usize {
+        let k = (0..3).filter(|&door| door != chosen).position(|door| door == opened).unwrap();
+        1 + 2 * chosen + k
+    }
+
+    /// (chosen door, opened door) of a state between 1 and 6
+    fn choice_of_state(state: usize) -> (usize, usize) {
+        let chosen = (state - 1) / 2;
+        let opened = (0..3).filter(|&door| door != chosen).nth((state - 1) % 2).unwrap();
+        (chosen, opened)
+    }
 }
 
 impl Env for MontyHallEnv {
     fn num_states(&self) -> usize {
-        2
+        8 // Choose a door, keep or switch once a door is opened (6 states), game over
     }
 
     fn num_actions(&self) -> usize {
-        2 // Keep or Switch
+        3 // Doors 0-2 on the first step, then keep (0) or switch (1)
     }
 
     fn num_rewards(&self) -> usize {
-        1 // Single reward at the end
+        2 // Lost or won, at the end
     }
 
     fn get_reward_vector(&self) -> Vec<f32> {
-        panic!("Not yet implemented");
+        vec![0.0, 1.0]
     }
 
     fn get_terminal_states(&self) -> Vec<usize> {
-        todo!()
+        vec![7]
     }
 
     fn get_reward(&self, _num: usize) -> f32 {
@@ -72,7 +86,11 @@ impl Env for MontyHallEnv {
     }
 
     fn state_id(&self) -> usize {
-        self.step
+        match self.step {
+            0 => 0,
+            1 => Self::choice_state(self.chosen_door.unwrap(), self.remaining_door.unwrap()),
+            _ => 7,
+        }
     }
 
     fn reset(&mut self) {
@@ -151,10 +169,78 @@ impl Env for MontyHallEnv {
     where
         Self: Sized,
     {
-        panic!("Not yet implemented");
+        let mut env = MontyHallEnv::new();
+        env.set_state(rand::thread_rng().gen_range(0..7));
+        env
     }
 
     fn transition_probability(&self, s: usize, a: usize, s_p: usize, r_index: usize) -> f32 {
-        panic!("Not yet implemented");
+        match s {
+            // Each of the two other doors is opened with probability 1/2:
+            // 1/3 * 1/2 if the chosen door wins, plus 1/3 if the third one does
+            0 if a < 3 && (1..7).contains(&s_p) && r_index == 0 => {
+                let (chosen, _) = Self::choice_of_state(s_p);
+                if chosen == a { 0.5 } else { 0.0 }
+            }
+            // The chosen door wins with probability 1/3, the other closed one with 2/3
+            1..=6 if a < 2 && s_p == 7 => {
+                let win = if a == 1 { 2.0 / 3.0 } else { 1.0 / 3.0 };
+                if r_index == 1 { win } else { 1.0 - win }
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn set_state(&mut self, state_id: usize) {
+        if state_id > 7 {
+            panic!("Invalid state: {}", state_id);
+        }
+        self.reset();
+        let mut rng = rand::thread_rng();
+        if (1..7).contains(&state_id) {
+            // Replay the choice until the host opens the same door
+            let (chosen, opened) = Self::choice_of_state(state_id);
+            loop {
+                self.reset();
+                self.step(chosen as i32);
+                if self.remaining_door == Some(opened) {
+                    break;
+                }
+            }
+        } else {
+            // Replay random moves up to the requested step
+            while self.step < state_id.min(2) {
+                let actions = self.available_actions();
+                self.step(actions[rng.gen_range(0..actions.len())]);
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Box::new(self.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        restore_clone(self, snapshot);
+    }
+
+    fn resample_hidden(&mut self) {
+        let mut rng = rand::thread_rng();
+        match self.step {
+            0 => self.winning_door = rng.gen_range(0..3),
+            1 => {
+                // Rejection sampling: keep a winning door only if the host
+                // opens the same door
+                let remaining_door = self.remaining_door;
+                loop {
+                    self.winning_door = rng.gen_range(0..3);
+                    self.reveal_remaining_door();
+                    if self.remaining_door == remaining_door {
+                        break;
+                    }
+                }
+            }
+            _ => {} // The game is over, nothing is hidden anymore
+        }
     }
 }
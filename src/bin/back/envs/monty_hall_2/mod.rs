@@ -1,10 +1,141 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::OnceLock;
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::Rng;
 use nalgebra::DVector;
 pub use crate::back::envs::basic_env::Env;
+use crate::back::envs::basic_env::{restore_clone, Snapshot};
 
+/// What the agent knows before its choice at `step`: the opened doors and the
+/// posterior of the winning door, as integer weights divided by their gcd
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct Belief {
+    step: usize,
+    /// Bit d set if door d is open
+    revealed: u8,
+    weights: [u64; 5],
+}
+
+impl Belief {
+    fn start() -> Self {
+        Belief { step: 0, revealed: 0, weights: [1; 5] }
+    }
+
+    /// Same state for every finished game
+    fn game_over() -> Self {
+        Belief { step: 4, revealed: 0, weights: [0; 5] }
+    }
+
+    /// Number of doors the host can open when `chosen` is picked and `winning` wins
+    fn host_options(&self, chosen: usize, winning: usize) -> u64 {
+        (0..5).filter(|&door| door != chosen && door != winning && self.revealed & (1 << door) == 0).count() as u64
+    }
+
+    /// Bayes update after picking `chosen` and seeing the host open `opened`, each
+    /// weight multiplied by 12 / (number of doors the host could open), 12 being
+    /// a multiple of all of them
+    fn after(&self, chosen: usize, opened: usize) -> Self {
+        let weights: [u64; 5] = std::array::from_fn(|winning| {
+            if self.weights[winning] > 0 && opened != winning {
+                self.weights[winning] * 12 / self.host_options(chosen, winning)
+            } else {
+                0
+            }
+        });
+        let gcd = weights.iter().fold(0, |a, &b| gcd(a, b));
+        Belief { step: self.step + 1, revealed: self.revealed | (1 << opened), weights: weights.map(|w| w / gcd) }
+    }
+
+    fn probability(&self, door: usize) -> f32 {
+        self.weights[door] as f32 / self.weights.iter().sum::<u64>() as f32
+    }
+
+    /// (opened door, probability) of each door the host may open after `chosen`
+    fn host_moves(&self, chosen: usize) -> Vec<(usize, f32)> {
+        (0..5)
+            .filter(|&door| door != chosen && self.revealed & (1 << door) == 0)
+            .map(|opened| {
+                let p = (0..5)
+                    .filter(|&winning| winning != opened && self.weights[winning] > 0)
+                    .map(|winning| self.probability(winning) / self.host_options(chosen, winning) as f32)
+                    .sum();
+                (opened, p)
+            })
+            .filter(|&(_, p)| p > 0.0)
+            .collect()
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Every belief reachable from the start, numbered in breadth-first order, with
+/// transitions[s][a] = (s', reward index, probability)
+struct Model {
+    beliefs: Vec<Belief>,
+    ids: HashMap<Belief, usize>,
+    transitions: Vec<Vec<Vec<(usize, usize, f32)>>>,
+}
+
+impl Model {
+    fn build() -> Self {
+        let mut model = Model { beliefs: Vec::new(), ids: HashMap::new(), transitions: Vec::new() };
+        let mut queue = VecDeque::from([model.id(Belief::start())]);
+        while let Some(s) = queue.pop_front() {
+            let belief = model.beliefs[s].clone();
+            let mut transitions_s = vec![Vec::new(); 5];
+            for (chosen, outcomes) in transitions_s.iter_mut().enumerate() {
+                if belief.revealed & (1 << chosen) != 0 {
+                    continue;
+                }
+                if belief.step == 3 {
+                    let win = belief.probability(chosen);
+                    let s_p = model.id(Belief::game_over());
+                    outcomes.extend([(s_p, 0, 1.0 - win), (s_p, 1, win)].into_iter().filter(|&(_, _, p)| p > 0.0));
+                } else {
+                    for (opened, p) in belief.host_moves(chosen) {
+                        let next = belief.after(chosen, opened);
+                        let is_new = !model.ids.contains_key(&next);
+                        let s_p = model.id(next);
+                        if is_new {
+                            queue.push_back(s_p);
+                        }
+                        outcomes.push((s_p, 0, p));
+                    }
+                }
+            }
+            model.transitions[s] = transitions_s;
+        }
+        model
+    }
+
+    /// Id of `belief`, numbered now if it is new
+    fn id(&mut self, belief: Belief) -> usize {
+        if let Some(&id) = self.ids.get(&belief) {
+            return id;
+        }
+        self.beliefs.push(belief.clone());
+        self.transitions.push(vec![Vec::new(); 5]);
+        self.ids.insert(belief, self.beliefs.len() - 1);
+        self.beliefs.len() - 1
+    }
+}
+
+fn model() -> &'static Model {
+    static MODEL: OnceLock<Model> = OnceLock::new();
+    MODEL.get_or_init(Model::build)
+}
+
+/// Monty Hall with 5 doors and 4 choices, the host opening a losing door at random
+/// after each of the first 3. A state is what the agent knows, i.e. the opened
+/// doors and the posterior of the winning door given the host's moves (see `Belief`).
+#[derive(Clone)]
 pub struct MontyHallLevel2Env {
     winning_door: usize,
     chosen_door: Option<usize>,
+    /// Door chosen at each step, to replay the game
+    choices: Vec<usize>,
     revealed_doors: Vec<usize>,
     step: usize,
     reward: f32,
@@ -18,6 +149,7 @@ impl MontyHallLevel2Env {
         MontyHallLevel2Env {
             winning_door,
             chosen_door: None,
+            choices: Vec::new(),
             revealed_doors: Vec::new(),
             step: 0,
             reward: 0.0,
@@ -26,35 +158,41 @@ impl MontyHallLevel2Env {
     }
 
     fn reveal_non_winning_door(&mut self) {
-        // Find a door to reveal that is not the chosen door and not the winning door
-        let door_to_reveal = (0..5)
+        // Pick a door to reveal that is not the chosen door and not the winning door
+        let doors: Vec<usize> = (0..5)
             .filter(|&door| Some(door) != self.chosen_door && door != self.winning_door && !self.revealed_doors.contains(&door))
-            .next()
-            .unwrap();
+            .collect();
+
+        self.revealed_doors.push(doors[rand::thread_rng().gen_range(0..doors.len())]);
+    }
 
-        self.revealed_doors.push(door_to_reveal);
+    fn belief(&self) -> Belief {
+        if self.is_game_over() {
+            return Belief::game_over();
+        }
+        self.choices.iter().zip(&self.revealed_doors).fold(Belief::start(), |belief, (&chosen, &opened)| belief.after(chosen, opened))
     }
 }
 
 impl Env for MontyHallLevel2Env {
     fn num_states(&self) -> usize {
-        5
+        model().beliefs.len()
     }
 
     fn num_actions(&self) -> usize {
-        4 // Switch to another door or stay (4 options total, including staying with the current door)
+        5 // Any door that is not revealed yet, including staying with the current one
     }
 
     fn num_rewards(&self) -> usize {
-        1 // Single reward at the end
+        2 // Lost or won, at the end
     }
 
     fn get_reward_vector(&self) -> Vec<f32> {
-        panic!("Not yet implemented");
+        vec![0.0, 1.0]
     }
 
     fn get_terminal_states(&self) -> Vec<usize> {
-        todo!()
+        vec![model().ids[&Belief::game_over()]]
     }
 
     fn get_reward(&self, _num: usize) -> f32 {
@@ -70,12 +208,13 @@ impl Env for MontyHallLevel2Env {
     }
 
     fn state_id(&self) -> usize {
-        self.step
+        model().ids[&self.belief()]
     }
 
     fn reset(&mut self) {
         self.winning_door = rand::thread_rng().gen_range(0..5);
         self.chosen_door = None;
+        self.choices.clear();
         self.revealed_doors.clear();
         self.step = 0;
         self.reward = 0.0;
@@ -130,6 +269,7 @@ impl Env for MontyHallLevel2Env {
         }
 
         self.chosen_door = Some(chosen_door);
+        self.choices.push(chosen_door);
 
         if self.step < 3 {
             // Reveal a door in the first 3 steps
@@ -154,10 +294,58 @@ impl Env for MontyHallLevel2Env {
     where
         Self: Sized,
     {
-        panic!("Not yet implemented");
+        let mut env = MontyHallLevel2Env::new();
+        let terminal = env.get_terminal_states()[0];
+        let state = loop {
+            let state = rand::thread_rng().gen_range(0..env.num_states());
+            if state != terminal {
+                break state;
+            }
+        };
+        env.set_state(state);
+        env
     }
 
     fn transition_probability(&self, s: usize, a: usize, s_p: usize, r_index: usize) -> f32 {
-        panic!("Not yet implemented");
+        model().transitions[s][a]
+            .iter()
+            .filter(|&&(next, r, _)| next == s_p && r == r_index)
+            .map(|&(_, _, p)| p)
+            .sum()
+    }
+
+    fn set_state(&mut self, state_id: usize) {
+        let target = match model().beliefs.get(state_id) {
+            Some(belief) => belief,
+            None => panic!("Invalid state: {}", state_id),
+        };
+        // Replay random moves up to the requested step until they lead to the state
+        let mut rng = rand::thread_rng();
+        loop {
+            self.reset();
+            while self.step < target.step {
+                let actions = self.available_actions();
+                self.step(actions[rng.gen_range(0..actions.len())]);
+            }
+            if self.state_id() == state_id {
+                break;
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Box::new(self.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        restore_clone(self, snapshot);
+    }
+
+    fn resample_hidden(&mut self) {
+        if self.is_game_over() {
+            return;
+        }
+        let weights = self.belief().weights;
+        self.winning_door = WeightedIndex::new(weights).unwrap().sample(&mut rand::thread_rng());
     }
 }
@@ -0,0 +1,133 @@
+//! Loader for third-party environment libraries.
+//!
+//! A plugin is a shared library exporting the manifest below, plus, for every
+//! env `<name>` it lists, the same functions as the secret envs
+//! (`<name>_num_states`, `<name>_step`, `<name>_available_actions`, ...):
+//!
+//! - `uint32_t rl_env_abi_version(void)` must return [`RL_ENV_ABI_VERSION`]
+//! - `const char *rl_env_list(void)` comma separated env names, owned by the plugin
+//! - `void *rl_env_new(const char *name)` creates the env `name`, NULL if unknown
+use libloading::Library;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::back::envs::secret_env::SecretEnv;
+
+/// Version of the plugin ABI understood by this build
+pub const RL_ENV_ABI_VERSION: u32 = 1;
+
+/// Per-env functions a plugin must export, prefixed by `<name>_`
+pub const ENV_SYMBOLS: [&str; 17] = [
+    "new",
+    "delete",
+    "num_states",
+    "num_actions",
+    "num_rewards",
+    "reward",
+    "transition_probability",
+    "reset",
+    "state_id",
+    "is_forbidden",
+    "is_game_over",
+    "display",
+    "available_actions",
+    "available_actions_len",
+    "available_actions_delete",
+    "step",
+    "score",
+];
+
+pub struct Plugin {
+    pub path: String,
+    pub env_names: Vec<String>,
+    lib: Arc<Library>,
+}
+
+impl Plugin {
+    /// Load the library at `path` and check its manifest and ABI version
+    ///
+    /// # Safety
+    /// Loading runs the library's initialisers, `path` must be a trusted plugin.
+    pub unsafe fn load(path: &str) -> Result<Self, String> {
+        let lib = Library::new(path).map_err(|e| format!("Failed to load plugin `{}`: {}", path, e))?;
+
+        let abi_version: libloading::Symbol<unsafe extern "C" fn() -> u32> = lib
+            .get(b"rl_env_abi_version")
+            .map_err(|_| format!("`{}` is not an env plugin: `rl_env_abi_version` is missing", path))?;
+        let version = abi_version();
+        if version != RL_ENV_ABI_VERSION {
+            return Err(format!(
+                "Plugin `{}` uses ABI version {}, but this program only supports version {}",
+                path, version, RL_ENV_ABI_VERSION
+            ));
+        }
+
+        let env_list: libloading::Symbol<unsafe extern "C" fn() -> *const c_char> = lib
+            .get(b"rl_env_list")
+            .map_err(|_| format!("Plugin `{}` does not export `rl_env_list`", path))?;
+        lib.get::<unsafe extern "C" fn(*const c_char) -> *mut c_void>(b"rl_env_new")
+            .map_err(|_| format!("Plugin `{}` does not export `rl_env_new`", path))?;
+
+        let list = env_list();
+        if list.is_null() {
+            return Err(format!("Plugin `{}` returned no env list", path));
+        }
+        let env_names: Vec<String> = CStr::from_ptr(list)
+            .to_string_lossy()
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        for name in &env_names {
+            for symbol in ENV_SYMBOLS {
+                let function_name = format!("{}_{}", name, symbol);
+                if lib.get::<*const c_void>(function_name.as_bytes()).is_err() {
+                    return Err(format!("Plugin `{}` lists `{}` but does not export `{}`", path, name, function_name));
+                }
+            }
+        }
+
+        Ok(Plugin { path: path.to_string(), env_names, lib: Arc::new(lib) })
+    }
+
+    /// Create the env `name` through the plugin's `rl_env_new`
+    ///
+    /// # Safety
+    /// The plugin must implement the per-env functions as documented above.
+    pub unsafe fn create(&self, name: &str) -> Result<SecretEnv, String> {
+        let rl_env_new: libloading::Symbol<unsafe extern "C" fn(*const c_char) -> *mut c_void> = self
+            .lib
+            .get(b"rl_env_new")
+            .map_err(|_| format!("Plugin `{}` does not export `rl_env_new`", self.path))?;
+        let c_name = CString::new(name).map_err(|_| format!("Invalid env name `{}`", name))?;
+        let env = rl_env_new(c_name.as_ptr());
+        if env.is_null() {
+            return Err(format!("Plugin `{}` could not create `{}`", self.path, name));
+        }
+        Ok(SecretEnv::from_raw(Arc::clone(&self.lib), name, env))
+    }
+}
+
+/// Load every shared library found in `dir`, keeping the errors for display
+///
+/// # Safety
+/// See [`Plugin::load`], every library in `dir` gets loaded.
+pub unsafe fn load_plugins_from_dir(dir: &str) -> Vec<Result<Plugin, String>> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_shared_library(path))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| Plugin::load(path)).collect()
+}
+
+fn is_shared_library(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("so") | Some("dylib") | Some("dll"))
+}
@@ -1,9 +1,20 @@
+pub mod opponents;
+
 use nalgebra::DVector;
+use rand::Rng;
 
 pub use crate::back::envs::basic_env::Env;
-
+use crate::back::envs::basic_env::{restore_clone, Snapshot};
+use crate::back::envs::rock_paper_scissors::opponents::{MimicFirstMove, Opponent};
+
+/// Rock-paper-scissors over a number of rounds against an `Opponent`. States: 0 in
+/// the first round, 1 + 3 * (round - 1) + first move in the next ones, the last one
+/// at the end. They are Markov when the opponent only looks at the round and the
+/// agent's first move, see `Opponent::probabilities`.
+#[derive(Clone)]
 pub struct RockPaperScissorsEnv {
     rounds: usize,
+    opponent: Box<dyn Opponent>,
     current_round: usize,
     agent_choices: Vec<usize>,
     opponent_choices: Vec<usize>,
@@ -12,13 +23,22 @@ pub struct RockPaperScissorsEnv {
 }
 
 impl RockPaperScissorsEnv {
+    /// Two rounds against `MimicFirstMove`
     pub fn new() -> Self {
+        Self::with(2, Box::new(MimicFirstMove))
+    }
+
+    pub fn with(rounds: usize, opponent: Box<dyn Opponent>) -> Self {
+        if rounds == 0 {
+            panic!("A game needs at least one round");
+        }
         RockPaperScissorsEnv {
-            rounds: 2,
+            rounds,
+            opponent,
             current_round: 0,
             agent_choices: Vec::new(),
             opponent_choices: Vec::new(),
-            rewards: vec![0; 2],
+            rewards: vec![0; rounds],
             total_score: 0,
         }
     }
@@ -31,18 +51,25 @@ impl RockPaperScissorsEnv {
         }
     }
 
-    fn generate_opponent_choice(&self) -> usize {
-        if self.current_round == 0 {
-            rand::random::<usize>() % 3 // Random choice for round 1
-        } else {
-            self.agent_choices[0] // Mimic agent's choice from round 1 in round 2
-        }
+    /// Whether `transition_probability` is available, i.e. the opponent's moves only
+    /// depend on the round and on the agent's first move in every round of the game
+    pub fn has_exact_model(&self) -> bool {
+        self.opponent.probabilities(0, None).is_some()
+            && (1..self.rounds).all(|round| (0..3).all(|first_move| self.opponent.probabilities(round, Some(first_move)).is_some()))
+    }
+
+    fn game_over_state(&self) -> usize {
+        1 + 3 * (self.rounds - 1)
+    }
+
+    fn generate_opponent_choice(&mut self) -> usize {
+        self.opponent.play(self.current_round, &self.agent_choices, &self.opponent_choices)
     }
 }
 
 impl Env for RockPaperScissorsEnv {
     fn num_states(&self) -> usize {
-        3 // Rock, Paper, Scissors
+        self.game_over_state() + 1 // First round, then each round and first move, game over
     }
 
     fn num_actions(&self) -> usize {
@@ -50,15 +77,15 @@ impl Env for RockPaperScissorsEnv {
     }
 
     fn num_rewards(&self) -> usize {
-        2 // Two rounds
+        3 // Lost, drawn or won round
     }
 
     fn get_reward_vector(&self) -> Vec<f32> {
-        panic!("Not yet implemented");
+        vec![-1.0, 0.0, 1.0]
     }
 
     fn get_terminal_states(&self) -> Vec<usize> {
-        panic!("Not yet implemented")
+        vec![self.game_over_state()]
     }
 
     fn get_reward(&self, num: usize) -> f32 {
@@ -74,15 +101,20 @@ impl Env for RockPaperScissorsEnv {
     }
 
     fn state_id(&self) -> usize {
-        self.current_round
+        match self.current_round {
+            0 => 0,
+            round if round >= self.rounds => self.game_over_state(),
+            round => 1 + 3 * (round - 1) + self.agent_choices[0],
+        }
     }
 
     fn reset(&mut self) {
         self.current_round = 0;
         self.agent_choices.clear();
         self.opponent_choices.clear();
-        self.rewards = vec![0; 2];
+        self.rewards = vec![0; self.rounds];
         self.total_score = 0;
+        self.opponent.reset();
     }
 
     fn display(&self) {
@@ -130,6 +162,7 @@ impl Env for RockPaperScissorsEnv {
 
         self.agent_choices.push(agent_choice);
         self.opponent_choices.push(opponent_choice);
+        self.opponent.observe(agent_choice, opponent_choice);
 
         let reward = Self::calculate_reward(agent_choice, opponent_choice);
         self.rewards[self.current_round] = reward;
@@ -146,10 +179,58 @@ impl Env for RockPaperScissorsEnv {
     where
         Self: Sized,
     {
-        panic!("Not yet implemented");
+        let mut env = RockPaperScissorsEnv::new();
+        env.set_state(rand::thread_rng().gen_range(0..env.game_over_state()));
+        env
     }
 
     fn transition_probability(&self, s: usize, a: usize, s_p: usize, r_index: usize) -> f32 {
-        panic!("Not yet implemented");
+        if a >= 3 || s >= self.game_over_state() {
+            return 0.0;
+        }
+        let (round, first_move) = if s == 0 { (0, a) } else { (1 + (s - 1) / 3, (s - 1) % 3) };
+        let next = if round + 1 >= self.rounds { self.game_over_state() } else { 1 + 3 * round + first_move };
+        if s_p != next {
+            return 0.0;
+        }
+        let probabilities = match self.opponent.probabilities(round, if round == 0 { None } else { Some(first_move) }) {
+            Some(probabilities) => probabilities,
+            None => panic!("The opponent has no exact model"),
+        };
+        (0..3)
+            .filter(|&opponent| (Self::calculate_reward(a, opponent) + 1) as usize == r_index)
+            .map(|opponent| probabilities[opponent])
+            .sum()
+    }
+
+    fn set_state(&mut self, state_id: usize) {
+        if state_id > self.game_over_state() {
+            panic!("Invalid state: {}", state_id);
+        }
+        // Play rounds up to the requested one, without keeping their score
+        self.reset();
+        let mut rng = rand::thread_rng();
+        let round = match state_id {
+            0 => 0,
+            s if s == self.game_over_state() => self.rounds,
+            s => 1 + (s - 1) / 3,
+        };
+        while self.current_round < round {
+            let action = if self.current_round == 0 && state_id < self.game_over_state() {
+                (state_id - 1) % 3
+            } else {
+                rng.gen_range(0..3)
+            };
+            self.step(action as i32);
+        }
+        self.total_score = 0;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Box::new(self.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        restore_clone(self, snapshot);
     }
 }
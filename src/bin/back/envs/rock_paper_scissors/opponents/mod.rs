@@ -0,0 +1,246 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Strategy of the opponent in `RockPaperScissorsEnv`, moves being 0 (rock),
+/// 1 (paper) and 2 (scissors)
+pub trait Opponent: Send + Sync {
+    /// Move for `round`, knowing the moves of the agent and its own ones in the
+    /// previous rounds
+    fn play(&mut self, round: usize, agent_moves: &[usize], own_moves: &[usize]) -> usize;
+
+    /// Sees the moves of a round once it is played
+    fn observe(&mut self, _agent_move: usize, _own_move: usize) {}
+
+    /// Forgets everything learned during the game, called when the env is reset
+    fn reset(&mut self) {}
+
+    /// Probabilities of its moves in `round` when they only depend on the round and
+    /// on the agent's first move (None in the first round), which makes the env an
+    /// MDP with an exact model. None for the other opponents.
+    fn probabilities(&self, _round: usize, _first_move: Option<usize>) -> Option<[f32; 3]> {
+        None
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent>;
+}
+
+impl Clone for Box<dyn Opponent> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Move beating `m`
+fn beats(m: usize) -> usize {
+    (m + 1) % 3
+}
+
+fn one_hot(m: usize) -> [f32; 3] {
+    let mut probabilities = [0.0; 3];
+    probabilities[m] = 1.0;
+    probabilities
+}
+
+/// Plays at random in the first round, then the agent's first move
+#[derive(Clone)]
+pub struct MimicFirstMove;
+
+impl Opponent for MimicFirstMove {
+    fn play(&mut self, round: usize, agent_moves: &[usize], _own_moves: &[usize]) -> usize {
+        if round == 0 {
+            rand::thread_rng().gen_range(0..3)
+        } else {
+            agent_moves[0]
+        }
+    }
+
+    fn probabilities(&self, _round: usize, first_move: Option<usize>) -> Option<[f32; 3]> {
+        match first_move {
+            None => Some([1.0 / 3.0; 3]),
+            Some(first_move) => Some(one_hot(first_move)),
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent> {
+        Box::new(self.clone())
+    }
+}
+
+/// Plays each move with probability 1/3
+#[derive(Clone)]
+pub struct UniformRandom;
+
+impl Opponent for UniformRandom {
+    fn play(&mut self, _round: usize, _agent_moves: &[usize], _own_moves: &[usize]) -> usize {
+        rand::thread_rng().gen_range(0..3)
+    }
+
+    fn probabilities(&self, _round: usize, _first_move: Option<usize>) -> Option<[f32; 3]> {
+        Some([1.0 / 3.0; 3])
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent> {
+        Box::new(self.clone())
+    }
+}
+
+/// Plays rock, paper and scissors with fixed probabilities
+#[derive(Clone)]
+pub struct Biased {
+    probabilities: [f32; 3],
+}
+
+impl Biased {
+    /// The weights are normalised, they must be non-negative and not all zero
+    pub fn new(weights: [f32; 3]) -> Self {
+        let total: f32 = weights.iter().sum();
+        if weights.iter().any(|&w| w < 0.0) || total <= 0.0 {
+            panic!("Invalid weights for a biased opponent: {:?}", weights);
+        }
+        Biased { probabilities: weights.map(|w| w / total) }
+    }
+}
+
+impl Opponent for Biased {
+    fn play(&mut self, _round: usize, _agent_moves: &[usize], _own_moves: &[usize]) -> usize {
+        WeightedIndex::new(self.probabilities).unwrap().sample(&mut rand::thread_rng())
+    }
+
+    fn probabilities(&self, _round: usize, _first_move: Option<usize>) -> Option<[f32; 3]> {
+        Some(self.probabilities)
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent> {
+        Box::new(self.clone())
+    }
+}
+
+/// Plays the agent's previous move, at random in the first round
+#[derive(Clone)]
+pub struct Copycat;
+
+impl Opponent for Copycat {
+    fn play(&mut self, _round: usize, agent_moves: &[usize], _own_moves: &[usize]) -> usize {
+        match agent_moves.last() {
+            Some(&last) => last,
+            None => rand::thread_rng().gen_range(0..3),
+        }
+    }
+
+    fn probabilities(&self, round: usize, first_move: Option<usize>) -> Option<[f32; 3]> {
+        match (round, first_move) {
+            (0, _) => Some([1.0 / 3.0; 3]),
+            (1, Some(first_move)) => Some(one_hot(first_move)),
+            _ => None,
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent> {
+        Box::new(self.clone())
+    }
+}
+
+/// Plays the move beating the agent's previous one, at random in the first round
+#[derive(Clone)]
+pub struct BeatsLast;
+
+impl Opponent for BeatsLast {
+    fn play(&mut self, _round: usize, agent_moves: &[usize], _own_moves: &[usize]) -> usize {
+        match agent_moves.last() {
+            Some(&last) => beats(last),
+            None => rand::thread_rng().gen_range(0..3),
+        }
+    }
+
+    fn probabilities(&self, round: usize, first_move: Option<usize>) -> Option<[f32; 3]> {
+        match (round, first_move) {
+            (0, _) => Some([1.0 / 3.0; 3]),
+            (1, Some(first_move)) => Some(one_hot(beats(first_move))),
+            _ => None,
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent> {
+        Box::new(self.clone())
+    }
+}
+
+/// Plays rock, paper, scissors, rock, ... from `start`
+#[derive(Clone)]
+pub struct Cyclic {
+    pub start: usize,
+}
+
+impl Opponent for Cyclic {
+    fn play(&mut self, round: usize, _agent_moves: &[usize], _own_moves: &[usize]) -> usize {
+        (self.start + round) % 3
+    }
+
+    fn probabilities(&self, round: usize, _first_move: Option<usize>) -> Option<[f32; 3]> {
+        Some(one_hot((self.start + round) % 3))
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent> {
+        Box::new(self.clone())
+    }
+}
+
+/// Plays the move beating the agent's most frequent move over all the games it has
+/// played, ties broken at random. The counts survive `reset`, so its moves depend on
+/// the previous games and it has no exact model.
+#[derive(Clone, Default)]
+pub struct FrequencyCounting {
+    counts: [usize; 3],
+}
+
+impl FrequencyCounting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Opponent for FrequencyCounting {
+    fn play(&mut self, _round: usize, _agent_moves: &[usize], _own_moves: &[usize]) -> usize {
+        let most = *self.counts.iter().max().unwrap();
+        let favourites: Vec<usize> = (0..3).filter(|&m| self.counts[m] == most).collect();
+        beats(*favourites.choose(&mut rand::thread_rng()).unwrap())
+    }
+
+    fn observe(&mut self, agent_move: usize, _own_move: usize) {
+        self.counts[agent_move] += 1;
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent> {
+        Box::new(self.clone())
+    }
+}
+
+/// Plays a fixed sequence of moves, starting over once it is exhausted
+#[derive(Clone)]
+pub struct Scripted {
+    moves: Vec<usize>,
+}
+
+impl Scripted {
+    pub fn new(moves: Vec<usize>) -> Self {
+        if moves.is_empty() || moves.iter().any(|&m| m > 2) {
+            panic!("Invalid scripted moves: {:?}", moves);
+        }
+        Scripted { moves }
+    }
+}
+
+impl Opponent for Scripted {
+    fn play(&mut self, round: usize, _agent_moves: &[usize], _own_moves: &[usize]) -> usize {
+        self.moves[round % self.moves.len()]
+    }
+
+    fn probabilities(&self, round: usize, _first_move: Option<usize>) -> Option<[f32; 3]> {
+        Some(one_hot(self.moves[round % self.moves.len()]))
+    }
+
+    fn clone_box(&self) -> Box<dyn Opponent> {
+        Box::new(self.clone())
+    }
+}
@@ -2,12 +2,34 @@ use libloading::Library;
 use nalgebra::DVector;
 use std::ffi::c_void;
 use std::sync::Arc;
-use crate::back::envs::basic_env::Env;
+use crate::back::envs::basic_env::{Env, Snapshot};
 
 pub struct SecretEnv {
     lib: Arc<Library>,
     env: *mut c_void,
     env_name: String,
+    // Actions played since the last reset, and the state ids they went through
+    history: ReplaySnapshot,
+}
+
+/// Snapshot of a secret env: the actions played since the last reset, replayed by
+/// `restore`, with the state ids and the score that the replay has to find again
+#[derive(Clone)]
+struct ReplaySnapshot {
+    actions: Vec<i32>,
+    states: Vec<usize>,
+    score: f32,
+}
+
+/// Path of our own `rl_envs` cdylib once built with `cargo build --release`
+pub fn rl_envs_library_path() -> &'static str {
+    #[cfg(target_os = "linux")]
+    let path = "./target/release/librl_envs.so";
+    #[cfg(target_os = "macos")]
+    let path = "./target/release/librl_envs.dylib";
+    #[cfg(windows)]
+    let path = "./target/release/rl_envs.dll";
+    path
 }
 
 impl SecretEnv {
@@ -23,11 +45,20 @@ impl SecretEnv {
         #[cfg(windows)]
         let path = "./libs/secret_envs.dll";
 
+        Self::from_library(path, env_name)
+    }
+
+    /// Load `env_name` from any library exposing the secret envs C ABI,
+    /// e.g. our own `rl_envs` cdylib (`line_world_new`, `grid_world_new`, ...)
+    ///
+    /// # Safety
+    /// The library must export the full secret envs ABI for `env_name`.
+    pub unsafe fn from_library(path: &str, env_name: &str) -> Self {
         // Convert the env_name to a String
         let env_name = env_name.to_string();
 
         // Load the library
-        let lib = Arc::new(Library::new(path).expect("Failed to load library"));
+        let lib = Arc::new(Library::new(path).unwrap_or_else(|e| panic!("Failed to load library `{}`: {}", path, e)));
 
         // Load the dynamic function based on `env_name`
         let secret_env_new: libloading::Symbol<unsafe extern fn() -> *mut c_void> = unsafe {
@@ -38,7 +69,22 @@ impl SecretEnv {
         // Call the function to create the environment
         let env = unsafe { secret_env_new() };
 
-        SecretEnv { lib, env, env_name }
+        Self::wrap(lib, env, env_name)
+    }
+
+    /// Wrap an env already created by `lib`, e.g. through a plugin's `rl_env_new`
+    ///
+    /// # Safety
+    /// `env` must be a live env created by `lib` for `env_name`, it is deleted on drop.
+    pub unsafe fn from_raw(lib: Arc<Library>, env_name: &str, env: *mut c_void) -> Self {
+        Self::wrap(lib, env, format!("{}_new", env_name))
+    }
+
+    fn wrap(lib: Arc<Library>, env: *mut c_void, env_name: String) -> Self {
+        let mut secret_env =
+            SecretEnv { lib, env, env_name, history: ReplaySnapshot { actions: Vec::new(), states: Vec::new(), score: 0.0 } };
+        secret_env.history.states.push(secret_env.state_id());
+        secret_env
     }
 
     /// Delete the dynamically loaded environment
@@ -153,6 +199,7 @@ impl Env for SecretEnv {
         unsafe {
             secret_env_reset(self.env);
         }
+        self.history = ReplaySnapshot { actions: Vec::new(), states: vec![self.state_id()], score: self.score() };
     }
 
     fn display(&self) {
@@ -244,6 +291,9 @@ impl Env for SecretEnv {
         unsafe {
             secret_env_step(self.env, action as usize);
         }
+        self.history.actions.push(action);
+        self.history.states.push(self.state_id());
+        self.history.score = self.score();
     }
 
     fn score(&self) -> f32 {
@@ -277,4 +327,44 @@ impl Env for SecretEnv {
         // Call the function and return the result
         unsafe { secret_env_transition_probability(s, a, s_p, r_index) }
     }
+
+    fn set_state(&mut self, _state_id: usize) {
+        panic!("Secret envs cannot be put in an arbitrary state");
+    }
+
+    /// The secret envs can't be copied, the snapshot is the list of actions since the
+    /// last reset. Restoring replays them, which is only exact for deterministic envs:
+    /// it panics as soon as the replay leaves the recorded states or score.
+    fn snapshot(&self) -> Snapshot {
+        Box::new(self.history.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        let recorded = snapshot.downcast_ref::<ReplaySnapshot>().expect("Snapshot was taken on another env").clone();
+        self.reset();
+        let mut replayed = 0;
+        let mut diverged = self.state_id() != recorded.states[0];
+        while !diverged && replayed < recorded.actions.len() {
+            self.step(recorded.actions[replayed]);
+            replayed += 1;
+            diverged = self.state_id() != recorded.states[replayed];
+        }
+        if diverged || self.score() != recorded.score {
+            panic!("Replaying `{}` diverged at step {}: the env is not deterministic and cannot be restored", self.env_name, replayed);
+        }
+    }
+}
+
+/// Another instance of the same env, in the same state through `restore`
+impl Clone for SecretEnv {
+    fn clone(&self) -> Self {
+        let secret_env_new: libloading::Symbol<unsafe extern "C" fn() -> *mut c_void> = unsafe {
+            self.lib
+                .get(self.env_name.as_bytes())
+                .unwrap_or_else(|_| panic!("Failed to load `{}`", self.env_name))
+        };
+        let mut copy = Self::wrap(Arc::clone(&self.lib), unsafe { secret_env_new() }, self.env_name.clone());
+        copy.restore(&self.snapshot());
+        copy
+    }
 }
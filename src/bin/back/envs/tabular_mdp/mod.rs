@@ -0,0 +1,218 @@
+use nalgebra::DVector;
+use rand::Rng;
+
+use crate::back::envs::basic_env::{restore_clone, Env, Snapshot};
+
+/// Finite MDP given by its transition table, e.g. a model learned from
+/// experience. The pairs (s, a) without any transition are not available.
+#[derive(Clone)]
+pub struct TabularMdp {
+    num_actions: usize,
+    rewards: Vec<f32>,
+    /// transitions[s][a] holds (s', reward index, probability)
+    transitions: Vec<Vec<Vec<(usize, usize, f32)>>>,
+    terminals: Vec<usize>,
+    start: usize,
+    current_state: usize,
+    current_score: f32,
+}
+
+impl TabularMdp {
+    pub fn new(
+        num_actions: usize,
+        rewards: Vec<f32>,
+        transitions: Vec<Vec<Vec<(usize, usize, f32)>>>,
+        terminals: Vec<usize>,
+        start: usize,
+    ) -> Self {
+        let num_states = transitions.len();
+        if start >= num_states {
+            panic!("Invalid start state: {}", start);
+        }
+        for (s, transitions_s) in transitions.iter().enumerate() {
+            if transitions_s.len() != num_actions {
+                panic!("State {} has {} actions instead of {}", s, transitions_s.len(), num_actions);
+            }
+            for (a, outcomes) in transitions_s.iter().enumerate() {
+                if outcomes.iter().any(|&(s_p, r_index, _)| s_p >= num_states || r_index >= rewards.len()) {
+                    panic!("Invalid transition from ({}, {})", s, a);
+                }
+                let total: f32 = outcomes.iter().map(|&(_, _, probability)| probability).sum();
+                if !outcomes.is_empty() && (total - 1.0).abs() > 1e-4 {
+                    panic!("Probabilities from ({}, {}) sum to {}", s, a, total);
+                }
+            }
+        }
+        TabularMdp { num_actions, rewards, transitions, terminals, start, current_state: start, current_score: 0.0 }
+    }
+
+    /// Sparse copy of an env whose dynamics are given by `transition_probability`,
+    /// starting where `env` starts after a reset
+    pub fn from_env(env: &mut dyn Env) -> Self {
+        let (num_states, num_actions, num_rewards) = (env.num_states(), env.num_actions(), env.num_rewards());
+        let transitions = (0..num_states)
+            .map(|s| {
+                (0..num_actions)
+                    .map(|a| {
+                        let mut outcomes = Vec::new();
+                        for s_p in 0..num_states {
+                            for r_index in 0..num_rewards {
+                                let probability = env.transition_probability(s, a, s_p, r_index);
+                                if probability > 0.0 {
+                                    outcomes.push((s_p, r_index, probability));
+                                }
+                            }
+                        }
+                        outcomes
+                    })
+                    .collect()
+            })
+            .collect();
+        env.reset();
+        TabularMdp::new(num_actions, env.get_reward_vector(), transitions, env.get_terminal_states(), env.state_id())
+    }
+
+    /// (s', reward index, probability) of every outcome of (s, a), empty if `a` is not available in `s`
+    pub fn outcomes(&self, s: usize, a: usize) -> &[(usize, usize, f32)] {
+        &self.transitions[s][a]
+    }
+
+    pub fn start_state(&self) -> usize {
+        self.start
+    }
+
+    /// Whether `s` ends the episode: a terminal state or one without any action
+    pub fn is_terminal(&self, s: usize) -> bool {
+        self.terminals.contains(&s) || self.transitions[s].iter().all(|outcomes| outcomes.is_empty())
+    }
+}
+
+impl Env for TabularMdp {
+    fn num_states(&self) -> usize {
+        self.transitions.len()
+    }
+
+    fn num_actions(&self) -> usize {
+        self.num_actions
+    }
+
+    fn num_rewards(&self) -> usize {
+        self.rewards.len()
+    }
+
+    fn get_reward_vector(&self) -> Vec<f32> {
+        self.rewards.clone()
+    }
+
+    fn get_terminal_states(&self) -> Vec<usize> {
+        self.terminals.clone()
+    }
+
+    fn get_reward(&self, num: usize) -> f32 {
+        self.rewards[num]
+    }
+
+    fn get_action_spaces(&self) -> Vec<usize> {
+        self.transitions
+            .iter()
+            .map(|transitions_s| transitions_s.iter().filter(|outcomes| !outcomes.is_empty()).count())
+            .collect()
+    }
+
+    fn p(&self, s: i32, a: i32, s_p: i32, r_index: i32) -> f32 {
+        self.transition_probability(s as usize, a as usize, s_p as usize, r_index as usize)
+    }
+
+    fn state_id(&self) -> usize {
+        self.current_state
+    }
+
+    fn reset(&mut self) {
+        self.current_state = self.start;
+        self.current_score = 0.0;
+    }
+
+    fn display(&self) {
+        println!("State {} of {}", self.current_state, self.num_states());
+        if self.is_game_over() {
+            println!("Episode over");
+        }
+    }
+
+    fn is_forbidden(&self, action: usize) -> bool {
+        !self.available_actions().iter().any(|&a| a as usize == action)
+    }
+
+    fn is_game_over(&self) -> bool {
+        self.terminals.contains(&self.current_state) || self.available_actions().is_empty()
+    }
+
+    fn available_actions(&self) -> DVector<i32> {
+        if self.terminals.contains(&self.current_state) {
+            return DVector::zeros(0);
+        }
+        let actions: Vec<i32> = (0..self.num_actions)
+            .filter(|&a| !self.transitions[self.current_state][a].is_empty())
+            .map(|a| a as i32)
+            .collect();
+        DVector::from_vec(actions)
+    }
+
+    fn step(&mut self, action: i32) {
+        if self.is_game_over() {
+            panic!("Trying to play when game is over!");
+        }
+        if self.is_forbidden(action as usize) {
+            panic!("Invalid action");
+        }
+
+        let outcomes = &self.transitions[self.current_state][action as usize];
+        let mut u: f32 = rand::thread_rng().gen();
+        let mut outcome = outcomes[outcomes.len() - 1];
+        for &candidate in outcomes {
+            if u < candidate.2 {
+                outcome = candidate;
+                break;
+            }
+            u -= candidate.2;
+        }
+        let (s_p, r_index, _) = outcome;
+        self.current_state = s_p;
+        self.current_score += self.rewards[r_index];
+    }
+
+    fn score(&self) -> f32 {
+        self.current_score
+    }
+
+    fn from_random_state() -> Self
+    where
+        Self: Sized,
+    {
+        panic!("A TabularMdp needs its transitions, see TabularMdp::new");
+    }
+
+    fn transition_probability(&self, s: usize, a: usize, s_p: usize, r_index: usize) -> f32 {
+        self.transitions[s][a]
+            .iter()
+            .filter(|&&(next, r, _)| next == s_p && r == r_index)
+            .map(|&(_, _, probability)| probability)
+            .sum()
+    }
+
+    fn set_state(&mut self, state_id: usize) {
+        if state_id >= self.num_states() {
+            panic!("Invalid state: {}", state_id);
+        }
+        self.current_state = state_id;
+        self.current_score = 0.0;
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Box::new(self.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        restore_clone(self, snapshot);
+    }
+}
@@ -0,0 +1,216 @@
+use std::ffi::{c_char, c_void, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::back::envs::plugin::RL_ENV_ABI_VERSION;
+
+/// Runs `f`, returning `default` instead of unwinding through the C caller.
+pub fn guard<T>(name: &str, default: T, f: impl FnOnce() -> T) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("`{}` panicked, returning a default value", name);
+            default
+        }
+    }
+}
+
+/// Hands a list of actions over to C, freed by `*_available_actions_delete`.
+pub fn actions_into_raw(actions: Vec<usize>) -> *const usize {
+    Box::into_raw(actions.into_boxed_slice()) as *const usize
+}
+
+/// # Safety
+/// `ptr` and `len` must come from `actions_into_raw` and not have been freed yet.
+pub unsafe fn actions_delete(ptr: *const usize, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr as *mut usize, len)));
+    }
+}
+
+/// Exports an `Env` with the same symbols as the secret envs library, all
+/// prefixed by `$prefix` (e.g. `line_world_new`, `line_world_step`, ...).
+///
+/// The functions without an env pointer (`num_states`, `transition_probability`, ...)
+/// answer from a lazily created instance of the environment.
+macro_rules! export_env {
+    ($module:ident, $prefix:literal, $env:ty) => {
+        pub mod $module {
+            use std::ffi::c_void;
+            use std::sync::OnceLock;
+            use crate::back::envs::basic_env::Env;
+            use crate::back::ffi::{actions_delete, actions_into_raw, guard};
+
+            fn model() -> &'static $env {
+                static MODEL: OnceLock<$env> = OnceLock::new();
+                MODEL.get_or_init(<$env>::new)
+            }
+
+            #[export_name = concat!($prefix, "_num_states")]
+            pub extern "C" fn num_states() -> usize {
+                guard(concat!($prefix, "_num_states"), 0, || model().num_states())
+            }
+
+            #[export_name = concat!($prefix, "_num_actions")]
+            pub extern "C" fn num_actions() -> usize {
+                guard(concat!($prefix, "_num_actions"), 0, || model().num_actions())
+            }
+
+            #[export_name = concat!($prefix, "_num_rewards")]
+            pub extern "C" fn num_rewards() -> usize {
+                guard(concat!($prefix, "_num_rewards"), 0, || model().num_rewards())
+            }
+
+            #[export_name = concat!($prefix, "_reward")]
+            pub extern "C" fn reward(i: usize) -> f32 {
+                guard(concat!($prefix, "_reward"), 0.0, || model().get_reward_vector()[i])
+            }
+
+            #[export_name = concat!($prefix, "_transition_probability")]
+            pub extern "C" fn transition_probability(s: usize, a: usize, s_p: usize, r_index: usize) -> f32 {
+                guard(concat!($prefix, "_transition_probability"), 0.0, || {
+                    model().transition_probability(s, a, s_p, r_index)
+                })
+            }
+
+            #[export_name = concat!($prefix, "_new")]
+            pub extern "C" fn new() -> *mut c_void {
+                guard(concat!($prefix, "_new"), std::ptr::null_mut(), || {
+                    Box::into_raw(Box::new(<$env>::new())) as *mut c_void
+                })
+            }
+
+            #[export_name = concat!($prefix, "_from_random_state")]
+            pub extern "C" fn from_random_state() -> *mut c_void {
+                guard(concat!($prefix, "_from_random_state"), std::ptr::null_mut(), || {
+                    Box::into_raw(Box::new(<$env>::from_random_state())) as *mut c_void
+                })
+            }
+
+            /// # Safety
+            /// `env` must come from `_new` or `_from_random_state` and be deleted only once.
+            #[export_name = concat!($prefix, "_delete")]
+            pub unsafe extern "C" fn delete(env: *mut c_void) {
+                if !env.is_null() {
+                    drop(Box::from_raw(env as *mut $env));
+                }
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_reset")]
+            pub unsafe extern "C" fn reset(env: *mut c_void) {
+                let env = &mut *(env as *mut $env);
+                guard(concat!($prefix, "_reset"), (), || env.reset())
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_state_id")]
+            pub unsafe extern "C" fn state_id(env: *const c_void) -> usize {
+                let env = &*(env as *const $env);
+                guard(concat!($prefix, "_state_id"), 0, || env.state_id())
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_is_forbidden")]
+            pub unsafe extern "C" fn is_forbidden(env: *const c_void, action: usize) -> bool {
+                let env = &*(env as *const $env);
+                guard(concat!($prefix, "_is_forbidden"), true, || {
+                    !env.available_actions().iter().any(|&a| a as usize == action)
+                })
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_is_game_over")]
+            pub unsafe extern "C" fn is_game_over(env: *const c_void) -> bool {
+                let env = &*(env as *const $env);
+                guard(concat!($prefix, "_is_game_over"), true, || env.is_game_over())
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_display")]
+            pub unsafe extern "C" fn display(env: *const c_void) {
+                let env = &*(env as *const $env);
+                guard(concat!($prefix, "_display"), (), || env.display())
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_available_actions")]
+            pub unsafe extern "C" fn available_actions(env: *const c_void) -> *const usize {
+                let env = &*(env as *const $env);
+                guard(concat!($prefix, "_available_actions"), std::ptr::null(), || {
+                    actions_into_raw(env.available_actions().iter().map(|&a| a as usize).collect())
+                })
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_available_actions_len")]
+            pub unsafe extern "C" fn available_actions_len(env: *const c_void) -> usize {
+                let env = &*(env as *const $env);
+                guard(concat!($prefix, "_available_actions_len"), 0, || env.available_actions().len())
+            }
+
+            /// # Safety
+            /// `actions` and `len` must come from `_available_actions` / `_available_actions_len`.
+            #[export_name = concat!($prefix, "_available_actions_delete")]
+            pub unsafe extern "C" fn available_actions_delete(actions: *const usize, len: usize) {
+                actions_delete(actions, len)
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_step")]
+            pub unsafe extern "C" fn step(env: *mut c_void, action: usize) {
+                let env = &mut *(env as *mut $env);
+                guard(concat!($prefix, "_step"), (), || env.step(action as i32))
+            }
+
+            /// # Safety
+            /// `env` must be a live pointer returned by `_new` or `_from_random_state`.
+            #[export_name = concat!($prefix, "_score")]
+            pub unsafe extern "C" fn score(env: *const c_void) -> f32 {
+                let env = &*(env as *const $env);
+                guard(concat!($prefix, "_score"), 0.0, || env.score())
+            }
+        }
+    };
+}
+
+export_env!(line_world, "line_world", crate::back::envs::line_world_env::LineEnv);
+export_env!(grid_world, "grid_world", crate::back::envs::grid_world_env::GridEnv);
+export_env!(monty_hall_1, "monty_hall_1", crate::back::envs::monty_hall_1::MontyHallEnv);
+export_env!(monty_hall_2, "monty_hall_2", crate::back::envs::monty_hall_2::MontyHallLevel2Env);
+export_env!(rock_paper_scissors, "rock_paper_scissors", crate::back::envs::rock_paper_scissors::RockPaperScissorsEnv);
+
+// Plugin manifest, see `envs::plugin`
+#[no_mangle]
+pub extern "C" fn rl_env_abi_version() -> u32 {
+    RL_ENV_ABI_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn rl_env_list() -> *const c_char {
+    c"line_world,grid_world,rock_paper_scissors,monty_hall_1,monty_hall_2".as_ptr()
+}
+
+/// # Safety
+/// `name` must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rl_env_new(name: *const c_char) -> *mut c_void {
+    if name.is_null() {
+        return std::ptr::null_mut();
+    }
+    match CStr::from_ptr(name).to_str() {
+        Ok("line_world") => line_world::new(),
+        Ok("grid_world") => grid_world::new(),
+        Ok("rock_paper_scissors") => rock_paper_scissors::new(),
+        Ok("monty_hall_1") => monty_hall_1::new(),
+        Ok("monty_hall_2") => monty_hall_2::new(),
+        _ => std::ptr::null_mut(),
+    }
+}
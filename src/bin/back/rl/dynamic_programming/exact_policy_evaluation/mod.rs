@@ -0,0 +1,118 @@
+use nalgebra::{DMatrix, DVector};
+use crate::back::envs::basic_env::Env;
+use crate::back::rl::dynamic_programming::sparse_model::sparse_model;
+
+/// V^π = (I - γ P^π)^-1 R^π, solved by LU decomposition in f64, where
+/// P^π(s, s') = Σ_a π(a|s) p(s'|s, a) and R^π(s) = Σ_a π(a|s) Σ p(s', r|s, a) r.
+/// `pi[s][a]` is indexed by action like for `iterative_policy_evaluation`, of
+/// which it gives the fixed point without the `theta` error. Panics when
+/// I - γ P^π is singular, e.g. γ = 1 with a policy that never terminates.
+pub fn exact_policy_evaluation(
+    pi: &[Vec<f32>],
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &dyn Env,
+    gamma: f32,
+) -> Vec<f32> {
+    let model = sparse_model(s, a, r, env);
+    let n = s.len();
+    let mut m = DMatrix::<f64>::identity(n, n);
+    let mut r_pi = DVector::<f64>::zeros(n);
+
+    for &state in s {
+        for (i, &action) in a.iter().enumerate() {
+            let p_action = pi[state][action] as f64;
+            if p_action == 0.0 {
+                continue;
+            }
+            for &(next_state, reward, p) in &model[state][i] {
+                m[(state, next_state)] -= gamma as f64 * p_action * p as f64;
+                r_pi[state] += p_action * p as f64 * reward as f64;
+            }
+        }
+    }
+
+    let v = m.lu().solve(&r_pi).expect("I - γP^π is singular: the policy does not terminate with γ = 1");
+    v.iter().map(|&x| x as f32).collect()
+}
+
+/// `exact_policy_evaluation` of the deterministic policy s -> `pi[s]`
+pub fn exact_policy_evaluation_deterministic(
+    pi: &[usize],
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &dyn Env,
+    gamma: f32,
+) -> Vec<f32> {
+    let num_actions = a.iter().max().map_or(0, |&action| action + 1);
+    let pi: Vec<Vec<f32>> = pi
+        .iter()
+        .map(|&action| {
+            let mut row = vec![0.0; num_actions];
+            row[action] = 1.0;
+            row
+        })
+        .collect();
+    exact_policy_evaluation(&pi, s, a, r, env, gamma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back::envs::grid_world_env::GridEnv;
+    use crate::back::envs::line_world_env::LineEnv;
+    use crate::back::rl::dynamic_programming::iterative_policy_evaluation::iterative_policy_evaluation;
+
+    const GAMMA: f32 = 0.9;
+    const THETA: f32 = 1e-4;
+
+    /// Both evaluations of `pi` agree within `THETA`. The iterative one stops at a
+    /// residual of θ(1 - γ)/γ, which bounds its error by θ.
+    fn assert_agree(env: &mut dyn Env, pi: &Vec<Vec<f32>>) {
+        let s: Vec<usize> = (0..env.num_states()).collect();
+        let a: Vec<usize> = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let exact = exact_policy_evaluation(pi, &s, &a, &r, env, GAMMA);
+        let iterative = iterative_policy_evaluation(pi, &s, &a, &r, env, GAMMA, THETA * (1.0 - GAMMA) / GAMMA);
+        for state in s {
+            assert!(
+                (exact[state] - iterative[state]).abs() < THETA,
+                "state {}: exact {} vs iterative {}",
+                state,
+                exact[state],
+                iterative[state]
+            );
+        }
+    }
+
+    fn uniform(env: &dyn Env) -> Vec<Vec<f32>> {
+        vec![vec![1.0 / env.num_actions() as f32; env.num_actions()]; env.num_states()]
+    }
+
+    /// Always takes `action`
+    fn deterministic(env: &dyn Env, action: usize) -> Vec<Vec<f32>> {
+        let mut row = vec![0.0; env.num_actions()];
+        row[action] = 1.0;
+        vec![row; env.num_states()]
+    }
+
+    #[test]
+    fn agrees_with_iterative_evaluation_on_line_env() {
+        let mut env = LineEnv::new();
+        let uniform = uniform(&env);
+        assert_agree(&mut env, &uniform);
+        let right = deterministic(&env, 1);
+        assert_agree(&mut env, &right);
+    }
+
+    #[test]
+    fn agrees_with_iterative_evaluation_on_grid_env() {
+        let mut env = GridEnv::new();
+        let uniform = uniform(&env);
+        assert_agree(&mut env, &uniform);
+        let down = deterministic(&env, 1);
+        assert_agree(&mut env, &down);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::back::envs::basic_env::Env;
+use crate::back::rl::dynamic_programming::sparse_model::{expected_value, sparse_model};
+
+/// Backward induction over `horizon` steps: V_H = 0, then for t = H - 1 down to 0
+/// V_t(s) = max_a Σ p(s', r | s, a) [r + γ V_{t+1}(s')], with π_t(s) the first
+/// action reaching it. Returns V_t(s) and π_t(s) indexed by [t][s], t from 0 to H
+/// for V and to H - 1 for π. γ = 1 is fine as the sum is finite. The actions
+/// without any outcome are not available, and the states without any available
+/// action are absorbing with V_t = 0.
+pub fn backward_induction(
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &dyn Env,
+    gamma: f32,
+    horizon: usize,
+) -> (Vec<Vec<f32>>, Vec<Vec<usize>>) {
+    let model = sparse_model(s, a, r, env);
+    let mut v = vec![vec![0.0f32; s.len()]; horizon + 1];
+    let mut pi = vec![vec![a[0]; s.len()]; horizon];
+
+    for t in (0..horizon).rev() {
+        for &state in s {
+            let mut best_value = f32::NEG_INFINITY;
+            for (i, outcomes) in model[state].iter().enumerate().filter(|(_, outcomes)| !outcomes.is_empty()) {
+                let value = expected_value(outcomes, &v[t + 1], gamma);
+                if value > best_value {
+                    best_value = value;
+                    pi[t][state] = a[i];
+                }
+            }
+            if best_value > f32::NEG_INFINITY {
+                v[t][state] = best_value;
+            }
+        }
+    }
+    (v, pi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back::envs::line_world_env::LineEnv;
+    use crate::back::envs::monty_hall_1::MontyHallEnv;
+    use crate::back::rl::dynamic_programming::value_iteration::value_iteration;
+
+    /// Action of the second step of Monty Hall, keeping being 0
+    const SWITCH: usize = 1;
+
+    #[test]
+    fn long_horizon_matches_value_iteration_on_line_env() {
+        let mut env = LineEnv::new();
+        let s: Vec<usize> = (0..env.num_states()).collect();
+        let a: Vec<usize> = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let gamma = 0.9;
+
+        // γ^200 is far below the tolerance, V_0 is V* up to that
+        let (v, _) = backward_induction(&s, &a, &r, &env, gamma, 200);
+        let (v_star, _) = value_iteration(&s, &a, &r, &mut env, gamma, 1e-6);
+        for &state in &s {
+            assert!((v[0][state] - v_star[state]).abs() < 1e-4, "state {}: V_0 {} vs VI {}", state, v[0][state], v_star[state]);
+        }
+    }
+
+    #[test]
+    fn monty_hall_switches_and_wins_two_thirds_of_the_time() {
+        let env = MontyHallEnv::new();
+        let s: Vec<usize> = (0..env.num_states()).collect();
+        let a: Vec<usize> = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+
+        let (v, pi) = backward_induction(&s, &a, &r, &env, 1.0, 2);
+        assert!((v[0][0] - 2.0 / 3.0).abs() < 1e-5, "V_0 of the start: {}", v[0][0]);
+        // Once a door is opened (states 1 to 6), switching wins 2/3 and keeping 1/3
+        for state in 1..=6 {
+            assert_eq!(pi[1][state], SWITCH, "state {}", state);
+            assert!((v[1][state] - 2.0 / 3.0).abs() < 1e-5, "V_1 of state {}: {}", state, v[1][state]);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+pub mod simplex;
+
+use crate::back::envs::basic_env::Env;
+use crate::back::rl::dynamic_programming::linear_programming::simplex::{LinearProgram, LpResult, Relation};
+use crate::back::rl::dynamic_programming::sparse_model::{expected_value, sparse_model, SparseModel};
+
+/// Optimal solution of the dual LP, in occupancy measures
+#[derive(Debug)]
+pub struct OccupancyMeasure {
+    /// x(s, a): expected discounted number of times `a` is taken in `s`, from μ
+    pub x: Vec<Vec<f32>>,
+    /// π(a|s) = x(s, a) / Σ_a' x(s, a'), uniform over the available actions where
+    /// `s` is never visited
+    pub pi: Vec<Vec<f32>>,
+    /// Σ x(s, a) R(s, a) = Σ μ(s) V^π(s)
+    pub objective: f32,
+}
+
+/// Pairs (state, index in `a`) of the modelled actions, the states without any
+/// being terminal with V = 0
+fn state_actions(model: &SparseModel) -> Vec<(usize, usize)> {
+    model
+        .iter()
+        .enumerate()
+        .flat_map(|(state, outcomes_s)| {
+            outcomes_s.iter().enumerate().filter(|(_, outcomes)| !outcomes.is_empty()).map(move |(i, _)| (state, i))
+        })
+        .collect()
+}
+
+/// Primal LP: minimise Σ_s V(s) subject to V(s) ≥ Σ p(s', r|s, a) [r + γ V(s')]
+/// for every available (s, a), whose solution is V*. V(s) = V⁺(s) - V⁻(s) as the
+/// simplex needs non-negative variables. Needs γ < 1, or every policy to terminate.
+pub fn linear_programming(
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &dyn Env,
+    gamma: f32,
+) -> (Vec<f32>, Vec<usize>) {
+    let model = sparse_model(s, a, r, env);
+    let n = s.len();
+    let pairs = state_actions(&model);
+    let mut terminal = vec![true; n];
+    for &(state, _) in &pairs {
+        terminal[state] = false;
+    }
+
+    // The variables of the terminal states stay at 0
+    let objective = (0..2 * n).map(|j| if terminal[j % n] { 0.0 } else if j < n { 1.0 } else { -1.0 }).collect();
+    let mut lp = LinearProgram::minimize(objective);
+    for (state, i) in pairs {
+        let mut coefficients = vec![(state, 1.0), (n + state, -1.0)];
+        let mut expected_reward = 0.0;
+        for &(next_state, reward, p) in &model[state][i] {
+            if !terminal[next_state] {
+                coefficients.push((next_state, -(gamma * p) as f64));
+                coefficients.push((n + next_state, (gamma * p) as f64));
+            }
+            expected_reward += (p * reward) as f64;
+        }
+        lp.add_constraint(coefficients, Relation::GreaterEq, expected_reward);
+    }
+
+    let x = match lp.solve() {
+        LpResult::Optimal(x, _) => x,
+        result => panic!("The primal LP of the MDP has no solution: {:?}", result),
+    };
+    let v: Vec<f32> = (0..n).map(|state| (x[state] - x[n + state]) as f32).collect();
+
+    // Greedy policy with respect to V*
+    let pi = model
+        .iter()
+        .map(|outcomes_s| {
+            let mut best_a = a[0];
+            let mut best_value = f32::NEG_INFINITY;
+            for (i, outcomes) in outcomes_s.iter().enumerate().filter(|(_, outcomes)| !outcomes.is_empty()) {
+                let value = expected_value(outcomes, &v, gamma);
+                if value > best_value {
+                    best_value = value;
+                    best_a = a[i];
+                }
+            }
+            best_a
+        })
+        .collect();
+    (v, pi)
+}
+
+/// Dual LP: maximise Σ x(s, a) R(s, a) over the occupancy measures x ≥ 0 subject
+/// to Σ_a x(s', a) - γ Σ_{s, a} p(s'|s, a) x(s, a) = μ(s') for every non-terminal s',
+/// plus Σ x(s, a) c(s, a) ≤ budget for each (c, budget) of `costs`. None when
+/// those constraints cannot all be met.
+fn solve_dual(
+    model: &SparseModel,
+    a: &[usize],
+    gamma: f32,
+    mu: &[f32],
+    costs: &[(&[Vec<f32>], f32)],
+) -> Option<OccupancyMeasure> {
+    let pairs = state_actions(model);
+    let rewards: Vec<f64> = pairs
+        .iter()
+        .map(|&(state, i)| model[state][i].iter().map(|&(_, reward, p)| (p * reward) as f64).sum())
+        .collect();
+    let mut lp = LinearProgram::maximize(rewards);
+
+    // Flow constraints, one per state with an available action
+    let mut flows: Vec<Vec<(usize, f64)>> = vec![Vec::new(); model.len()];
+    for (j, &(state, i)) in pairs.iter().enumerate() {
+        flows[state].push((j, 1.0));
+        for &(next_state, _, p) in &model[state][i] {
+            flows[next_state].push((j, -(gamma * p) as f64));
+        }
+    }
+    for (state, flow) in flows.into_iter().enumerate() {
+        if flow.iter().any(|&(j, _)| pairs[j].0 == state) {
+            lp.add_constraint(flow, Relation::Equal, mu[state] as f64);
+        }
+    }
+
+    for &(cost, budget) in costs {
+        let coefficients = pairs.iter().enumerate().map(|(j, &(state, i))| (j, cost[state][a[i]] as f64)).collect();
+        lp.add_constraint(coefficients, Relation::LessEq, budget as f64);
+    }
+
+    let (x_pairs, objective) = match lp.solve() {
+        LpResult::Optimal(x, objective) => (x, objective),
+        LpResult::Infeasible => return None,
+        LpResult::Unbounded => panic!("The dual LP of the MDP is unbounded"),
+    };
+
+    let num_actions = a.iter().max().map_or(0, |&action| action + 1);
+    let mut x = vec![vec![0.0f32; num_actions]; model.len()];
+    for (j, &(state, i)) in pairs.iter().enumerate() {
+        x[state][a[i]] = x_pairs[j] as f32;
+    }
+    let pi = x
+        .iter()
+        .enumerate()
+        .map(|(state, x_s)| {
+            let total: f32 = x_s.iter().sum();
+            if total > 0.0 {
+                x_s.iter().map(|x| x / total).collect()
+            } else {
+                let available: Vec<usize> = pairs.iter().filter(|&&(s, _)| s == state).map(|&(_, i)| a[i]).collect();
+                let mut row = vec![0.0; num_actions];
+                for &action in &available {
+                    row[action] = 1.0 / available.len() as f32;
+                }
+                row
+            }
+        })
+        .collect();
+    Some(OccupancyMeasure { x, pi, objective: objective as f32 })
+}
+
+/// Dual of `linear_programming`: the occupancy measure of an optimal policy from
+/// the initial distribution `mu`, the objective being Σ μ(s) V*(s)
+pub fn linear_programming_dual(
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &dyn Env,
+    gamma: f32,
+    mu: &[f32],
+) -> OccupancyMeasure {
+    let model = sparse_model(s, a, r, env);
+    solve_dual(&model, a, gamma, mu, &[]).expect("The dual LP of the MDP is infeasible")
+}
+
+/// Constrained MDP: best policy from `mu` whose expected discounted cost
+/// Σ x(s, a) `cost[s][a]` stays within `budget`. The optimal policy can be
+/// stochastic. None when no policy meets the budget.
+#[allow(clippy::too_many_arguments)]
+pub fn constrained_mdp(
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &dyn Env,
+    gamma: f32,
+    mu: &[f32],
+    cost: &[Vec<f32>],
+    budget: f32,
+) -> Option<OccupancyMeasure> {
+    let model = sparse_model(s, a, r, env);
+    solve_dual(&model, a, gamma, mu, &[(cost, budget)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back::envs::grid_world_env::GridEnv;
+    use crate::back::rl::dynamic_programming::policy_iteration::policy_iteration;
+    use crate::back::rl::dynamic_programming::value_iteration::value_iteration;
+
+    const GAMMA: f32 = 0.9;
+    const TOLERANCE: f32 = 1e-3;
+
+    #[test]
+    fn primal_matches_value_and_policy_iteration_on_grid_env() {
+        let mut env = GridEnv::new();
+        let s: Vec<usize> = (0..env.num_states()).collect();
+        let a: Vec<usize> = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let t = env.get_terminal_states();
+
+        let (v_lp, _) = linear_programming(&s, &a, &r, &env, GAMMA);
+        let (v_vi, _) = value_iteration(&s, &a, &r, &mut env, GAMMA, 1e-6);
+        let (_, v_pi) = policy_iteration(&s, &a, &r, &t, &mut env, GAMMA, 1e-6);
+        for state in s {
+            assert!((v_lp[state] - v_vi[state]).abs() < TOLERANCE, "state {}: LP {} vs VI {}", state, v_lp[state], v_vi[state]);
+            assert!((v_lp[state] - v_pi[state]).abs() < TOLERANCE, "state {}: LP {} vs PI {}", state, v_lp[state], v_pi[state]);
+        }
+    }
+
+    #[test]
+    fn dual_objective_is_mu_weighted_optimal_value_on_grid_env() {
+        let env = GridEnv::new();
+        let s: Vec<usize> = (0..env.num_states()).collect();
+        let a: Vec<usize> = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let mu = vec![1.0 / s.len() as f32; s.len()];
+
+        let (v, _) = linear_programming(&s, &a, &r, &env, GAMMA);
+        let occupancy = linear_programming_dual(&s, &a, &r, &env, GAMMA, &mu);
+        let expected: f32 = mu.iter().zip(&v).map(|(m, v)| m * v).sum();
+        assert!(
+            (occupancy.objective - expected).abs() < TOLERANCE,
+            "dual objective {} vs Σ μ V* {}",
+            occupancy.objective,
+            expected
+        );
+    }
+}
@@ -0,0 +1,227 @@
+const EPS: f64 = 1e-9;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Relation {
+    LessEq,
+    GreaterEq,
+    Equal,
+}
+
+#[derive(Debug)]
+pub enum LpResult {
+    /// Values of the variables and of the objective
+    Optimal(Vec<f64>, f64),
+    Infeasible,
+    Unbounded,
+}
+
+/// min or max c·x subject to a_i·x (≤, ≥ or =) b_i and x ≥ 0, solved by a small
+/// dense simplex, enough for the LPs of tabular MDPs. Free variables have to be
+/// split by the caller.
+pub struct LinearProgram {
+    /// Minimised objective, the opposite of c when maximising
+    objective: Vec<f64>,
+    sense: f64,
+    constraints: Vec<Constraint>,
+}
+
+/// Σ coefficient * x[variable] (relation) rhs, with sparse coefficients
+struct Constraint {
+    coefficients: Vec<(usize, f64)>,
+    relation: Relation,
+    rhs: f64,
+}
+
+impl LinearProgram {
+    pub fn minimize(objective: Vec<f64>) -> Self {
+        LinearProgram { objective, sense: 1.0, constraints: Vec::new() }
+    }
+
+    pub fn maximize(objective: Vec<f64>) -> Self {
+        LinearProgram { objective: objective.iter().map(|c| -c).collect(), sense: -1.0, constraints: Vec::new() }
+    }
+
+    pub fn num_variables(&self) -> usize {
+        self.objective.len()
+    }
+
+    /// Adds Σ coefficient * x[variable] (relation) rhs, repeated variables adding up
+    pub fn add_constraint(&mut self, coefficients: Vec<(usize, f64)>, relation: Relation, rhs: f64) {
+        if let Some(&(variable, _)) = coefficients.iter().find(|&&(variable, _)| variable >= self.num_variables()) {
+            panic!("Invalid variable {} in a constraint of an LP with {} variables", variable, self.num_variables());
+        }
+        self.constraints.push(Constraint { coefficients, relation, rhs });
+    }
+
+    /// Phase 1 finds a feasible basis by minimising the sum of artificial
+    /// variables, phase 2 then minimises the objective from it. Bland's rule
+    /// chooses the pivots, so that degenerate LPs do not cycle.
+    pub fn solve(&self) -> LpResult {
+        let n = self.num_variables();
+        let m = self.constraints.len();
+
+        // Slack / surplus and artificial columns, with rhs ≥ 0
+        let num_slacks = self.constraints.iter().filter(|constraint| constraint.relation != Relation::Equal).count();
+        let first_artificial = n + num_slacks;
+        let mut rows = Vec::with_capacity(m);
+        let mut basis = Vec::with_capacity(m);
+        let mut num_artificials = 0;
+        let mut slack = n;
+        for Constraint { coefficients, relation, rhs } in &self.constraints {
+            let sign = if *rhs < 0.0 { -1.0 } else { 1.0 };
+            let mut row = vec![0.0; first_artificial + m + 1];
+            for &(variable, coefficient) in coefficients {
+                row[variable] += sign * coefficient;
+            }
+            row[first_artificial + m] = sign * rhs;
+            let relation = match (relation, sign < 0.0) {
+                (Relation::LessEq, true) => Relation::GreaterEq,
+                (Relation::GreaterEq, true) => Relation::LessEq,
+                (relation, _) => *relation,
+            };
+            match relation {
+                Relation::LessEq => {
+                    row[slack] = 1.0;
+                    basis.push(slack);
+                    slack += 1;
+                }
+                Relation::GreaterEq => {
+                    row[slack] = -1.0;
+                    slack += 1;
+                    row[first_artificial + num_artificials] = 1.0;
+                    basis.push(first_artificial + num_artificials);
+                    num_artificials += 1;
+                }
+                Relation::Equal => {
+                    row[first_artificial + num_artificials] = 1.0;
+                    basis.push(first_artificial + num_artificials);
+                    num_artificials += 1;
+                }
+            }
+            rows.push(row);
+        }
+        // Drops the unused artificial columns
+        let num_columns = first_artificial + num_artificials;
+        for row in rows.iter_mut() {
+            let rhs = row[first_artificial + m];
+            row.truncate(num_columns);
+            row.push(rhs);
+        }
+        let mut tableau = Tableau { rows, basis };
+
+        // Phase 1
+        if num_artificials > 0 {
+            let mut cost = vec![0.0; num_columns];
+            cost[first_artificial..].fill(1.0);
+            tableau.minimize(&cost, num_columns);
+            let infeasibility: f64 = tableau.value_of(&cost);
+            let scale = 1.0 + self.constraints.iter().map(|constraint| constraint.rhs.abs()).sum::<f64>();
+            if infeasibility > 1e-7 * scale {
+                return LpResult::Infeasible;
+            }
+            tableau.drive_out_artificials(first_artificial);
+        }
+
+        // Phase 2
+        let mut cost = vec![0.0; num_columns];
+        cost[..n].copy_from_slice(&self.objective);
+        if !tableau.minimize(&cost, first_artificial) {
+            return LpResult::Unbounded;
+        }
+        let mut x = vec![0.0; n];
+        for (i, &column) in tableau.basis.iter().enumerate() {
+            if column < n {
+                x[column] = tableau.rhs(i).max(0.0);
+            }
+        }
+        let objective = self.objective.iter().zip(&x).map(|(c, x)| c * x).sum::<f64>();
+        LpResult::Optimal(x, self.sense * objective)
+    }
+}
+
+/// Rows of the constraints in canonical form for `basis`, the right-hand side last
+struct Tableau {
+    rows: Vec<Vec<f64>>,
+    basis: Vec<usize>,
+}
+
+impl Tableau {
+    fn rhs(&self, i: usize) -> f64 {
+        self.rows[i][self.rows[i].len() - 1]
+    }
+
+    /// cost·x of the current basic solution
+    fn value_of(&self, cost: &[f64]) -> f64 {
+        self.basis.iter().enumerate().map(|(i, &column)| cost[column] * self.rhs(i)).sum()
+    }
+
+    fn pivot(&mut self, row: usize, column: usize) {
+        let pivot = self.rows[row][column];
+        self.rows[row].iter_mut().for_each(|x| *x /= pivot);
+        let pivot_row = self.rows[row].clone();
+        for (i, other) in self.rows.iter_mut().enumerate() {
+            let factor = other[column];
+            if i != row && factor != 0.0 {
+                other.iter_mut().zip(&pivot_row).for_each(|(x, p)| *x -= factor * p);
+            }
+        }
+        self.basis[row] = column;
+    }
+
+    /// Minimises cost·x with the columns before `num_allowed` only allowed to
+    /// enter the basis. Returns false when the LP is unbounded.
+    fn minimize(&mut self, cost: &[f64], num_allowed: usize) -> bool {
+        loop {
+            // Bland's rule: first column with a negative reduced cost
+            let entering = (0..num_allowed).find(|&column| {
+                !self.basis.contains(&column)
+                    && cost[column]
+                        - self.basis.iter().enumerate().map(|(i, &b)| cost[b] * self.rows[i][column]).sum::<f64>()
+                        < -EPS
+            });
+            let Some(column) = entering else {
+                return true;
+            };
+
+            // Ratio test, ties broken by the smallest basic column
+            let mut leaving: Option<(usize, f64)> = None;
+            for i in 0..self.rows.len() {
+                if self.rows[i][column] > EPS {
+                    let ratio = self.rhs(i) / self.rows[i][column];
+                    leaving = match leaving {
+                        Some((best, best_ratio))
+                            if ratio > best_ratio + EPS
+                                || (ratio > best_ratio - EPS && self.basis[best] < self.basis[i]) =>
+                        {
+                            Some((best, best_ratio))
+                        }
+                        _ => Some((i, ratio)),
+                    };
+                }
+            }
+            match leaving {
+                Some((row, _)) => self.pivot(row, column),
+                None => return false,
+            }
+        }
+    }
+
+    /// Pivots the artificial variables left in the basis at zero out of it, and
+    /// drops the redundant rows where that is impossible
+    fn drive_out_artificials(&mut self, first_artificial: usize) {
+        let mut i = 0;
+        while i < self.rows.len() {
+            if self.basis[i] >= first_artificial {
+                match (0..first_artificial).find(|&column| self.rows[i][column].abs() > EPS) {
+                    Some(column) => self.pivot(i, column),
+                    None => {
+                        self.rows.remove(i);
+                        self.basis.remove(i);
+                        continue;
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+}
@@ -1,3 +1,9 @@
 pub mod policy_iteration;
 pub mod value_iteration;
-pub mod iterative_policy_evaluation;
\ No newline at end of file
+pub mod iterative_policy_evaluation;
+pub mod rtdp;
+pub mod sparse_model;
+pub mod q_value_iteration;
+pub mod exact_policy_evaluation;
+pub mod linear_programming;
+pub mod finite_horizon;
\ No newline at end of file
@@ -1,100 +1,168 @@
 use crate::back::envs::basic_env::Env;
-use rand::Rng;
+use crate::back::rl::dynamic_programming::sparse_model::{available_actions, expected_value, first_available_actions, sparse_model, SparseModel};
 
-fn iterative_policy_evaluation(
-    pi: &Vec<usize>,
-    s: &Vec<usize>,
-    r: &Vec<f32>,
-    t: &Vec<usize>,
-    env: &mut dyn Env,
-    gamma: f32,
-    theta: f32,
-) -> Vec<f32> {
-    let mut rng = rand::thread_rng();
-    let mut v: Vec<f32> = (0..s.len()).map(|_| rng.gen()).collect();
-    for &T in t {
-        v[T] = 0.0;
-    }
-    loop {
-        let mut delta: f32 = 0.0;
-
-        for &state in s {
-            let v_old = v[state];
-            let action = pi[state];
-            let mut total = 0.0;
+/// How much work a run of policy iteration took
+#[derive(Debug)]
+pub struct PolicyIterationStats {
+    pub improvements: usize,
+    pub evaluation_sweeps: usize,
+}
 
-            for &next_state in s {
-                for r_id in 0..r.len() {
-                    let reward = r[r_id];
-                    total += env.transition_probability(state, action, next_state, r_id) * (reward + gamma * v[next_state]);
-                }
-            }
-            v[state] = total;
-            delta = delta.max((v_old - v[state]).abs());
-        }
+/// Keeps `old_action` unless another action is better by more than `tolerance`,
+/// so that the policy does not flip-flop between equally good actions
+pub fn improved_action(q_s: &[(usize, f32)], old_action: usize, tolerance: f32) -> usize {
+    let old_value = q_s.iter().find(|&&(a, _)| a == old_action).map(|&(_, q)| q).unwrap_or(f32::NEG_INFINITY);
+    let (best_a, best_q) = q_s.iter().fold((old_action, old_value), |best, &(a, q)| if q > best.1 { (a, q) } else { best });
+    if best_q > old_value + tolerance {
+        best_a
+    } else {
+        old_action
+    }
+}
 
-        if delta < theta {
-            break;
+/// One in-place sweep of the evaluation of `pi`, returns the largest change
+fn evaluation_sweep(model: &SparseModel, a: &[usize], pi: &[usize], terminal: &[bool], v: &mut [f32], gamma: f32) -> f32 {
+    let mut delta: f32 = 0.0;
+    for state in 0..v.len() {
+        if terminal[state] {
+            continue;
         }
+        let i = a.iter().position(|&action| action == pi[state]).unwrap();
+        let v_old = v[state];
+        v[state] = expected_value(&model[state][i], v, gamma);
+        delta = delta.max((v_old - v[state]).abs());
     }
-    v
+    delta
 }
 
 pub fn policy_iteration(
-    s: &Vec<usize>,
-    a: &Vec<usize>,
-    r: &Vec<f32>,
-    t: &Vec<usize>,
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    t: &[usize],
     env: &mut dyn Env,
     gamma: f32,
     theta: f32,
 ) -> (Vec<usize>, Vec<f32>) {
-    // Initialization
-    let mut rng = rand::thread_rng();
-    let mut v: Vec<f32> = (0..s.len()).map(|_| rng.gen()).collect();
+    let (pi, v, _) = modified_policy_iteration(s, a, r, t, env, gamma, theta, usize::MAX);
+    (pi, v)
+}
+
+/// Policy iteration whose evaluation stops after `k` sweeps, or sooner once a
+/// sweep changes V by less than `theta` (`k` = `usize::MAX` is policy iteration,
+/// `k` = 1 is close to value iteration). Each evaluation starts from the values
+/// of the previous policy, and it ends when the policy is stable and V has converged.
+#[allow(clippy::too_many_arguments)]
+pub fn modified_policy_iteration(
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    t: &[usize],
+    env: &mut dyn Env,
+    gamma: f32,
+    theta: f32,
+    k: usize,
+) -> (Vec<usize>, Vec<f32>, PolicyIterationStats) {
+    let model = sparse_model(s, a, r, env);
+    let terminal: Vec<bool> = s.iter().map(|state| t.contains(state)).collect();
+    let mut v = vec![0.0f32; s.len()];
+    let mut pi = first_available_actions(&model, a);
+    let mut stats = PolicyIterationStats { improvements: 0, evaluation_sweeps: 0 };
 
-    // Set terminal states to 0.0
-    for &T in t {
-        v[T] = 0.0;
-    }
-    let mut pi: Vec<usize> = vec![0; s.len()];
     loop {
         // Policy Evaluation
-        v = iterative_policy_evaluation(&pi, &s, &r, &t, env, gamma, theta);
+        let mut delta = f32::INFINITY;
+        for _ in 0..k.max(1) {
+            delta = evaluation_sweep(&model, a, &pi, &terminal, &mut v, gamma);
+            stats.evaluation_sweeps += 1;
+            if delta < theta {
+                break;
+            }
+        }
 
-        // Policy Improvement
+        // Policy Improvement, over the available actions
         let mut stable_policy = true;
-
         for &state in s {
-            let old_action = pi[state];
-            let mut best_a = 0;
-            let mut best_a_score = -f32::INFINITY;
-
-            for &action in a {
-                let mut total = 0.0;
-
-                for &next_state in s {
-                    for r_id in 0..r.len() {
-                        let reward = r[r_id];
-                        total += env.transition_probability(state, action, next_state, r_id) * (reward + gamma * v[next_state]);
-                    }
-                }
-
-                if total > best_a_score {
-                    best_a = action;
-                    best_a_score = total;
-                }
-            }
-
-            pi[state] = best_a;
-            if best_a != old_action {
+            let q_s: Vec<(usize, f32)> = available_actions(&model, state)
+                .into_iter()
+                .map(|i| (a[i], expected_value(&model[state][i], &v, gamma)))
+                .collect();
+            let best_a = improved_action(&q_s, pi[state], theta);
+            if best_a != pi[state] {
+                pi[state] = best_a;
                 stable_policy = false;
             }
         }
+        stats.improvements += 1;
 
-        if stable_policy {
+        if stable_policy && delta < theta {
             break;
         }
     }
-    (pi, v)
+    (pi, v, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back::envs::grid_world_env::GridEnv;
+    use crate::back::rl::dynamic_programming::exact_policy_evaluation::exact_policy_evaluation_deterministic;
+    use crate::back::rl::dynamic_programming::q_value_iteration::q_policy_iteration;
+    use crate::back::rl::dynamic_programming::value_iteration::value_iteration;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    const GAMMA: f32 = 0.9;
+    const THETA: f32 = 1e-6;
+    const TOLERANCE: f32 = 1e-3;
+
+    /// Policy found from (S, A, R, terminal states) on the env
+    type Planner = fn(&[usize], &[usize], &[f32], &[usize], &mut GridEnv) -> Vec<usize>;
+
+    /// Open 4x4 grid with the goal in the far corner: going down or right are equally
+    /// good in most cells, the ties that used to keep the policy from being stable
+    fn tied_grid() -> GridEnv {
+        GridEnv::with_layout(4, 4, 0, vec![(15, 1.0)], vec![])
+    }
+
+    /// Runs `planner` on a fresh `tied_grid`, failing if it does not stop in time,
+    /// and returns the exact value of its policy
+    fn value_of_policy(planner: Planner) -> Vec<f32> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut env = tied_grid();
+            let s: Vec<usize> = (0..env.num_states()).collect();
+            let a: Vec<usize> = (0..env.num_actions()).collect();
+            let r = env.get_reward_vector();
+            let t = env.get_terminal_states();
+            let pi = planner(&s, &a, &r, &t, &mut env);
+            sender.send(exact_policy_evaluation_deterministic(&pi, &s, &a, &r, &env, GAMMA)).unwrap();
+        });
+        receiver.recv_timeout(Duration::from_secs(30)).expect("The planner did not terminate")
+    }
+
+    #[test]
+    fn terminates_on_tied_actions_and_matches_value_iteration() {
+        let mut env = tied_grid();
+        let s: Vec<usize> = (0..env.num_states()).collect();
+        let a: Vec<usize> = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let (v_star, _) = value_iteration(&s, &a, &r, &mut env, GAMMA, THETA);
+
+        let planners: [(&str, Planner); 4] = [
+            ("policy iteration", |s, a, r, t, env| policy_iteration(s, a, r, t, env, GAMMA, THETA).0),
+            ("modified policy iteration, k = 1", |s, a, r, t, env| modified_policy_iteration(s, a, r, t, env, GAMMA, THETA, 1).0),
+            ("modified policy iteration, k = ∞", |s, a, r, t, env| {
+                modified_policy_iteration(s, a, r, t, env, GAMMA, THETA, usize::MAX).0
+            }),
+            ("Q policy iteration", |s, a, r, _, env| q_policy_iteration(s, a, r, env, GAMMA, THETA).1),
+        ];
+        for (name, planner) in planners {
+            let v = value_of_policy(planner);
+            for &state in &s {
+                assert!((v[state] - v_star[state]).abs() < TOLERANCE, "{}, state {}: {} vs VI {}", name, state, v[state], v_star[state]);
+            }
+        }
+    }
 }
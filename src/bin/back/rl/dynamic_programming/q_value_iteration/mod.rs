@@ -0,0 +1,120 @@
+use crate::back::envs::basic_env::Env;
+use crate::back::rl::dynamic_programming::policy_iteration::improved_action;
+use crate::back::rl::dynamic_programming::sparse_model::{available_actions, first_available_actions, sparse_model, SparseModel};
+
+/// Available action of `state` with the largest Q, the first one on ties, and its Q.
+/// `a[0]` and 0 in a state without any available action.
+fn greedy(model: &SparseModel, a: &[usize], q: &[Vec<f32>], state: usize) -> (usize, f32) {
+    available_actions(model, state)
+        .into_iter()
+        .map(|i| (a[i], q[state][a[i]]))
+        .fold(None, |best: Option<(usize, f32)>, (action, value)| match best {
+            Some((_, best_value)) if best_value >= value => best,
+            _ => Some((action, value)),
+        })
+        .unwrap_or((a[0], 0.0))
+}
+
+/// Σ p(s', r | s, a) [r + γ Q(s', π(s'))], or max_a' Q(s', a') over the available
+/// actions without a policy
+fn q_target(
+    model: &SparseModel,
+    a: &[usize],
+    q: &[Vec<f32>],
+    state: usize,
+    i: usize,
+    gamma: f32,
+    pi: Option<&[usize]>,
+) -> f32 {
+    model[state][i]
+        .iter()
+        .map(|&(next_state, reward, p)| {
+            let next_value = match pi {
+                Some(pi) => q[next_state][pi[next_state]],
+                None => greedy(model, a, q, next_state).1,
+            };
+            p * (reward + gamma * next_value)
+        })
+        .sum()
+}
+
+/// Value iteration on Q: Q(s, a) ← Σ p(s', r | s, a) [r + γ max_a' Q(s', a')]
+/// until a sweep changes Q by less than `theta`. Returns Q*(s, a), indexed by
+/// action, and the greedy policy.
+pub fn q_value_iteration(
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &mut dyn Env,
+    gamma: f32,
+    theta: f32,
+) -> (Vec<Vec<f32>>, Vec<usize>) {
+    let model = sparse_model(s, a, r, env);
+    let mut q = vec![vec![0.0f32; env.num_actions()]; s.len()];
+
+    loop {
+        let mut delta: f32 = 0.0;
+        for &state in s {
+            for i in available_actions(&model, state) {
+                let q_old = q[state][a[i]];
+                q[state][a[i]] = q_target(&model, a, &q, state, i, gamma, None);
+                delta = delta.max((q_old - q[state][a[i]]).abs());
+            }
+        }
+        if delta < theta {
+            break;
+        }
+    }
+
+    let pi = s.iter().map(|&state| greedy(&model, a, &q, state).0).collect();
+    (q, pi)
+}
+
+/// Policy iteration on Q: evaluates Q^π, then makes π greedy with respect to it,
+/// keeping the current action on ties (see `improved_action`). Returns Q^π, indexed
+/// by action, and π once it is stable.
+pub fn q_policy_iteration(
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &mut dyn Env,
+    gamma: f32,
+    theta: f32,
+) -> (Vec<Vec<f32>>, Vec<usize>) {
+    let model = sparse_model(s, a, r, env);
+    let mut q = vec![vec![0.0f32; env.num_actions()]; s.len()];
+    let mut pi = first_available_actions(&model, a);
+
+    loop {
+        // Policy Evaluation, from the Q of the previous policy
+        loop {
+            let mut delta: f32 = 0.0;
+            for &state in s {
+                for i in available_actions(&model, state) {
+                    let q_old = q[state][a[i]];
+                    q[state][a[i]] = q_target(&model, a, &q, state, i, gamma, Some(&pi));
+                    delta = delta.max((q_old - q[state][a[i]]).abs());
+                }
+            }
+            if delta < theta {
+                break;
+            }
+        }
+
+        // Policy Improvement, over the available actions
+        let mut stable_policy = true;
+        for &state in s {
+            let q_s: Vec<(usize, f32)> = available_actions(&model, state).into_iter().map(|i| (a[i], q[state][a[i]])).collect();
+            let best_a = improved_action(&q_s, pi[state], theta);
+            if best_a != pi[state] {
+                pi[state] = best_a;
+                stable_policy = false;
+            }
+        }
+
+        if stable_policy {
+            break;
+        }
+    }
+    (q, pi)
+}
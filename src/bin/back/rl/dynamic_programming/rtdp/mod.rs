@@ -0,0 +1,227 @@
+use kdam::tqdm;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::back::envs::basic_env::Env;
+use crate::back::envs::tabular_mdp::TabularMdp;
+
+/// Q(s, a) = Σ p(s', r | s, a) [r + γ V(s')] of each action available in `s`
+fn action_values(mdp: &TabularMdp, v: &[f32], s: usize, gamma: f32) -> Vec<(usize, f32)> {
+    (0..mdp.num_actions())
+        .filter(|&a| !mdp.outcomes(s, a).is_empty())
+        .map(|a| {
+            let q = mdp.outcomes(s, a).iter().map(|&(s_p, r_index, p)| p * (mdp.get_reward(r_index) + gamma * v[s_p])).sum();
+            (a, q)
+        })
+        .collect()
+}
+
+fn best_value(values: &[(usize, f32)]) -> f32 {
+    values.iter().map(|&(_, q)| q).fold(f32::NEG_INFINITY, f32::max)
+}
+
+/// Next state drawn from the outcomes of (s, a)
+fn sample_next_state(outcomes: &[(usize, usize, f32)], rng: &mut impl Rng) -> usize {
+    let mut u: f32 = rng.gen();
+    for &(s_p, _, p) in outcomes {
+        if u < p {
+            return s_p;
+        }
+        u -= p;
+    }
+    outcomes[outcomes.len() - 1].0
+}
+
+/// Synchronous value iteration sweep: every V(s) is computed from the previous V.
+/// Returns the new V and the largest change.
+fn synchronous_sweep(mdp: &TabularMdp, v: &[f32], gamma: f32) -> (Vec<f32>, f32) {
+    let mut new_v = v.to_vec();
+    let mut delta: f32 = 0.0;
+    for s in 0..mdp.num_states() {
+        if mdp.is_terminal(s) {
+            continue;
+        }
+        new_v[s] = best_value(&action_values(mdp, v, s, gamma));
+        delta = delta.max((new_v[s] - v[s]).abs());
+    }
+    (new_v, delta)
+}
+
+/// Value iteration by trajectory sampling: expected updates only on the states
+/// met along trajectories from the start state. V is kept between trajectories.
+pub struct TrajectorySampling {
+    pub v: Vec<f32>,
+    /// Number of state backups so far
+    pub backups: usize,
+    /// Whether each state was backed up at least once
+    pub updated: Vec<bool>,
+}
+
+impl TrajectorySampling {
+    /// V = `v_init` everywhere but on the terminal states
+    pub fn new(mdp: &TabularMdp, v_init: f32) -> Self {
+        let v = (0..mdp.num_states()).map(|s| if mdp.is_terminal(s) { 0.0 } else { v_init }).collect();
+        TrajectorySampling { v, backups: 0, updated: vec![false; mdp.num_states()] }
+    }
+
+    /// One trajectory of at most `max_steps` steps from the start state: each state
+    /// met is backed up, then left with an ε-greedy action on the backed up values
+    /// (greedy for `epsilon` = 0, which is RTDP). Returns the largest change of V.
+    pub fn episode(&mut self, mdp: &TabularMdp, gamma: f32, epsilon: f32, max_steps: usize, rng: &mut impl Rng) -> f32 {
+        let mut delta: f32 = 0.0;
+        let mut s = mdp.start_state();
+        for _ in 0..max_steps {
+            if mdp.is_terminal(s) {
+                break;
+            }
+            let values = action_values(mdp, &self.v, s, gamma);
+            let best = best_value(&values);
+            delta = delta.max((best - self.v[s]).abs());
+            self.v[s] = best;
+            self.backups += 1;
+            self.updated[s] = true;
+
+            let a = if rng.gen::<f32>() < epsilon {
+                values.choose(rng).unwrap().0
+            } else {
+                let ties: Vec<usize> = values.iter().filter(|&&(_, q)| q == best).map(|&(a, _)| a).collect();
+                *ties.choose(rng).unwrap()
+            };
+            s = sample_next_state(mdp.outcomes(s, a), rng);
+        }
+        delta
+    }
+
+    /// Backs up the states reachable from the start by greedy actions, all the
+    /// ties included, whose Bellman residual is at least `theta`. Returns whether
+    /// there were none, i.e. the greedy policy from the start has converged.
+    pub fn is_solved(&mut self, mdp: &TabularMdp, gamma: f32, theta: f32) -> bool {
+        let mut solved = true;
+        let mut seen = vec![false; mdp.num_states()];
+        let mut stack = vec![mdp.start_state()];
+        seen[mdp.start_state()] = true;
+        while let Some(s) = stack.pop() {
+            if mdp.is_terminal(s) {
+                continue;
+            }
+            let values = action_values(mdp, &self.v, s, gamma);
+            let best = best_value(&values);
+            self.backups += 1;
+            self.updated[s] = true;
+            if (best - self.v[s]).abs() >= theta {
+                solved = false;
+            }
+            self.v[s] = best;
+            for &(a, q) in &values {
+                if best - q < theta {
+                    for &(s_p, _, _) in mdp.outcomes(s, a) {
+                        if !seen[s_p] {
+                            seen[s_p] = true;
+                            stack.push(s_p);
+                        }
+                    }
+                }
+            }
+        }
+        solved
+    }
+
+    /// Greedy policy with respect to V, 0 in the terminal states
+    pub fn greedy_policy(&self, mdp: &TabularMdp, gamma: f32) -> Vec<usize> {
+        (0..mdp.num_states())
+            .map(|s| {
+                action_values(mdp, &self.v, s, gamma)
+                    .into_iter()
+                    .fold((0, f32::NEG_INFINITY), |best, (a, q)| if q > best.1 { (a, q) } else { best })
+                    .0
+            })
+            .collect()
+    }
+}
+
+/// Trajectories from V = `v_init` until one changes V by less than `theta` and
+/// the greedy policy from the start is solved, see `TrajectorySampling::is_solved`
+fn solve(
+    mdp: &TabularMdp,
+    gamma: f32,
+    theta: f32,
+    v_init: f32,
+    epsilon: f32,
+    max_episodes: usize,
+    rng: &mut impl Rng,
+) -> TrajectorySampling {
+    let mut sampling = TrajectorySampling::new(mdp, v_init);
+    let max_steps = 10 * mdp.num_states();
+    for _ in 0..max_episodes {
+        if sampling.episode(mdp, gamma, epsilon, max_steps, rng) < theta && sampling.is_solved(mdp, gamma, theta) {
+            break;
+        }
+    }
+    sampling
+}
+
+/// Real-Time Dynamic Programming (Sutton & Barto, section 8.7): value iteration
+/// backing up only the states visited by greedy trajectories from the start state.
+/// With an optimistic `v_init` (at least V*), it converges on the states relevant
+/// to the start without ever sweeping the others.
+pub fn rtdp(mdp: &TabularMdp, gamma: f32, theta: f32, v_init: f32, max_episodes: usize) -> TrajectorySampling {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    solve(mdp, gamma, theta, v_init, 0.0, max_episodes, &mut rng)
+}
+
+/// State backups synchronous value iteration, RTDP and ε-greedy trajectory
+/// sampling need to converge (changes below `theta`), all starting from V =
+/// `v_init`, with the number of distinct states each of them backed up and its
+/// error on V(start). The sampling methods are averaged over `num_runs` runs.
+pub fn compare_backups_to_convergence(
+    mdp: &TabularMdp,
+    gamma: f32,
+    theta: f32,
+    v_init: f32,
+    epsilon: f32,
+    num_runs: usize,
+) -> Vec<(String, f32, f32, f32)> {
+    let start = mdp.start_state();
+    let non_terminal = (0..mdp.num_states()).filter(|&s| !mdp.is_terminal(s)).count();
+
+    let mut v = TrajectorySampling::new(mdp, v_init).v;
+    let mut sweeps = 0;
+    loop {
+        let (new_v, delta) = synchronous_sweep(mdp, &v, gamma);
+        v = new_v;
+        sweeps += 1;
+        if delta < theta {
+            break;
+        }
+    }
+    // V* for the errors, by sweeps run well past `theta`
+    let mut v_star = v.clone();
+    loop {
+        let (new_v, delta) = synchronous_sweep(mdp, &v_star, gamma);
+        v_star = new_v;
+        if delta < 1e-7 {
+            break;
+        }
+    }
+    let mut results = vec![(
+        "Synchronous VI".to_string(),
+        (sweeps * non_terminal) as f32,
+        non_terminal as f32,
+        (v[start] - v_star[start]).abs(),
+    )];
+
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    for (name, eps) in [("RTDP".to_string(), 0.0), (format!("Trajectory sampling (ε = {})", epsilon), epsilon)] {
+        let (mut backups, mut updated, mut error) = (0.0, 0.0, 0.0);
+        for _ in tqdm!(0..num_runs, position = 0) {
+            let sampling = solve(mdp, gamma, theta, v_init, eps, usize::MAX, &mut rng);
+            backups += sampling.backups as f32 / num_runs as f32;
+            updated += sampling.updated.iter().filter(|&&u| u).count() as f32 / num_runs as f32;
+            error += (sampling.v[start] - v_star[start]).abs() / num_runs as f32;
+        }
+        results.push((name, backups, updated, error));
+    }
+    results
+}
@@ -0,0 +1,42 @@
+use crate::back::envs::basic_env::Env;
+
+/// outcomes[s][i] = (s', reward, probability) of every outcome of (s, a[i])
+pub type SparseModel = Vec<Vec<Vec<(usize, f32, f32)>>>;
+
+/// The outcomes of `env` with a non-zero probability, read once so that the
+/// backups do not go through all the (s', r) pairs
+pub fn sparse_model(s: &[usize], a: &[usize], r: &[f32], env: &dyn Env) -> SparseModel {
+    s.iter()
+        .map(|&state| {
+            a.iter()
+                .map(|&action| {
+                    let mut outcomes = Vec::new();
+                    for &next_state in s {
+                        for (r_id, &reward) in r.iter().enumerate() {
+                            let probability = env.transition_probability(state, action, next_state, r_id);
+                            if probability > 0.0 {
+                                outcomes.push((next_state, reward, probability));
+                            }
+                        }
+                    }
+                    outcomes
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Indices in `a` of the actions available in `state`, i.e. with an outcome
+pub fn available_actions(model: &SparseModel, state: usize) -> Vec<usize> {
+    (0..model[state].len()).filter(|&i| !model[state][i].is_empty()).collect()
+}
+
+/// First available action of each state, `a[0]` in the states without any
+pub fn first_available_actions(model: &SparseModel, a: &[usize]) -> Vec<usize> {
+    (0..model.len()).map(|state| available_actions(model, state).first().map_or(a[0], |&i| a[i])).collect()
+}
+
+/// Σ p(s', r | s, a) [r + γ V(s')] over the `outcomes` of (s, a)
+pub fn expected_value(outcomes: &[(usize, f32, f32)], v: &[f32], gamma: f32) -> f32 {
+    outcomes.iter().map(|&(next_state, reward, p)| p * (reward + gamma * v[next_state])).sum()
+}
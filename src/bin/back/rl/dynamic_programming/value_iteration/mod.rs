@@ -1,73 +1,253 @@
-use rand::Rng;
+use rand::seq::SliceRandom;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::time::{Duration, Instant};
 use crate::back::envs::basic_env::Env;
+use crate::back::rl::dynamic_programming::sparse_model::{expected_value, sparse_model, SparseModel};
+
+/// Order in which value iteration backs up the states
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BackupScheme {
+    /// Synchronous sweeps (Jacobi): a sweep only reads the values of the previous one
+    Jacobi,
+    /// In-place sweeps in state order (Gauss-Seidel)
+    GaussSeidel,
+    /// In-place sweeps in a new random order each time
+    RandomOrder,
+    /// One state at a time, the one with the largest Bellman residual first
+    Prioritized,
+}
+
+pub const BACKUP_SCHEMES: [BackupScheme; 4] =
+    [BackupScheme::Jacobi, BackupScheme::GaussSeidel, BackupScheme::RandomOrder, BackupScheme::Prioritized];
+
+/// What a run of value iteration cost
+#[derive(Debug)]
+pub struct ValueIterationReport {
+    /// Sweeps over the states, or backups / number of states for `Prioritized`
+    pub sweeps: usize,
+    /// Bellman backups computed, including the residual updates of `Prioritized`
+    pub backups: usize,
+    /// Largest Bellman residual after each sweep
+    pub residuals: Vec<f32>,
+    /// Time spent in the backups, the model being read from the env once beforehand
+    pub duration: Duration,
+}
+
+/// max_a Σ p(s', r | s, a) [r + γ V(s')] over the available actions and the first
+/// action reaching it. The actions without any outcome are not available, and a
+/// state without any available action is absorbing with V = 0.
+fn backup(model: &SparseModel, a: &[usize], v: &[f32], state: usize, gamma: f32) -> (f32, usize) {
+    let mut best_value = f32::NEG_INFINITY;
+    let mut best_a = a[0];
+    for (i, outcomes) in model[state].iter().enumerate().filter(|(_, outcomes)| !outcomes.is_empty()) {
+        let total = expected_value(outcomes, v, gamma);
+        if total > best_value {
+            best_value = total;
+            best_a = a[i];
+        }
+    }
+    if best_value == f32::NEG_INFINITY {
+        best_value = 0.0;
+    }
+    (best_value, best_a)
+}
 
 pub fn value_iteration(
-    s: &Vec<usize>,  // States (as indices)
-    a: &Vec<usize>,  // Actions
-    r: &Vec<f32>,    // Rewards
+    s: &[usize],  // States (as indices)
+    a: &[usize],  // Actions
+    r: &[f32],    // Rewards
     env: &mut dyn Env, // Transition probabilities
     gamma: f32,
     theta: f32,
 ) -> (Vec<f32>, Vec<usize>) {
-    // Initialize value function V(s) arbitrarily (e.g., all zeros)
+    let (v, pi, _) = value_iteration_with(s, a, r, env, gamma, theta, BackupScheme::GaussSeidel);
+    (v, pi)
+}
+
+/// Value iteration with the given backup scheme, until the largest residual of a
+/// sweep is below `theta`. The policy is greedy with respect to the last backup
+/// of each state, so it needs no extra pass.
+pub fn value_iteration_with(
+    s: &[usize],
+    a: &[usize],
+    r: &[f32],
+    env: &mut dyn Env,
+    gamma: f32,
+    theta: f32,
+    scheme: BackupScheme,
+) -> (Vec<f32>, Vec<usize>, ValueIterationReport) {
+    let model = sparse_model(s, a, r, env);
+    let start = Instant::now();
+
+    // V = 0, which the absorbing states keep
     let mut rng = rand::thread_rng();
-    let mut v: Vec<f32> = (0..s.len()).map(|_| rng.gen()).collect();
+    let mut v = vec![0.0f32; s.len()];
+    let mut pi = vec![a[0]; s.len()];
+    let mut report = ValueIterationReport { sweeps: 0, backups: 0, residuals: Vec::new(), duration: Duration::ZERO };
 
-    loop {
-        let mut delta: f32 = 0.0;
+    if scheme == BackupScheme::Prioritized {
+        prioritized_backups(&model, a, &mut v, &mut pi, gamma, theta, &mut report);
+    } else {
+        let mut order = s.to_vec();
+        loop {
+            let old_v = if scheme == BackupScheme::Jacobi { v.clone() } else { Vec::new() };
+            if scheme == BackupScheme::RandomOrder {
+                order.shuffle(&mut rng);
+            }
+            let mut delta: f32 = 0.0;
+            for &state in &order {
+                let (best_value, best_a) =
+                    backup(&model, a, if scheme == BackupScheme::Jacobi { &old_v } else { &v }, state, gamma);
+                delta = delta.max((best_value - v[state]).abs());
+                v[state] = best_value;
+                pi[state] = best_a;
+            }
+            report.sweeps += 1;
+            report.backups += order.len();
+            report.residuals.push(delta);
+            if delta < theta {
+                break;
+            }
+        }
+    }
 
-        for &state in s {
-            let v_old = v[state];
-            let mut best_value = -f32::INFINITY;
+    report.duration = start.elapsed();
+    (v, pi, report)
+}
 
-            for &action in a {
-                let mut total = 0.0;
+/// State waiting in the queue of prioritized value iteration with its residual
+struct Entry {
+    residual: f32,
+    state: usize,
+}
 
-                for &next_state in s {
-                    for r_id in 0..r.len() {
-                        let reward = r[r_id];
-                        total += env.transition_probability(state, action, next_state, r_id) * (reward + gamma * v[next_state]);
-                    }
-                }
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
 
-                if total > best_value {
-                    best_value = total;
-                }
-            }
+impl Eq for Entry {}
 
-            v[state] = best_value;
-            delta = delta.max((v_old - v[state]).abs());
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.residual.total_cmp(&other.residual)
+    }
+}
+
+/// Backs up the state with the largest residual until they are all below `theta`,
+/// updating the residuals of its predecessors after each backup
+fn prioritized_backups(
+    model: &SparseModel,
+    a: &[usize],
+    v: &mut [f32],
+    pi: &mut [usize],
+    gamma: f32,
+    theta: f32,
+    report: &mut ValueIterationReport,
+) {
+    let num_states = v.len();
+    let mut predecessors = vec![Vec::new(); num_states];
+    for (state, outcomes_s) in model.iter().enumerate() {
+        for &(next_state, _, _) in outcomes_s.iter().flatten() {
+            if predecessors[next_state].last() != Some(&state) {
+                predecessors[next_state].push(state);
+            }
         }
+    }
 
-        if delta < theta {
+    // Entries whose residual is not the current one of their state are stale
+    let mut residuals = vec![0.0f32; num_states];
+    let mut queue = BinaryHeap::new();
+    for state in 0..num_states {
+        let (best_value, best_a) = backup(model, a, v, state, gamma);
+        residuals[state] = (best_value - v[state]).abs();
+        pi[state] = best_a;
+        queue.push(Entry { residual: residuals[state], state });
+    }
+    report.backups += num_states;
+
+    let mut updates = 0;
+    loop {
+        let top = loop {
+            match queue.peek() {
+                Some(entry) if entry.residual != residuals[entry.state] => {
+                    queue.pop();
+                }
+                top => break top.map(|entry| entry.residual).unwrap_or(0.0),
+            }
+        };
+        if updates % num_states == 0 || top < theta {
+            report.residuals.push(top);
+        }
+        if top < theta {
             break;
         }
-    }
 
-    // Extract the deterministic policy π(s) from the optimal value function V(s)
-    let mut pi = vec![0; s.len()];
+        let state = queue.pop().unwrap().state;
+        let (best_value, best_a) = backup(model, a, v, state, gamma);
+        v[state] = best_value;
+        pi[state] = best_a;
+        residuals[state] = 0.0;
+        updates += 1;
 
-    for &state in s {
-        let mut best_a = 0;
-        let mut best_a_score = -f32::INFINITY;
+        for &pred in &predecessors[state] {
+            let (best_value, best_a) = backup(model, a, v, pred, gamma);
+            residuals[pred] = (best_value - v[pred]).abs();
+            pi[pred] = best_a;
+            queue.push(Entry { residual: residuals[pred], state: pred });
+        }
+        report.backups += 1 + predecessors[state].len();
+    }
+    report.sweeps = updates.div_ceil(num_states);
+}
 
-        for &action in a {
-            let mut total = 0.0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back::envs::grid_world_env::GridEnv;
 
-            for &next_state in s {
-                for r_id in 0..r.len() {
-                    let reward = r[r_id];
-                    total += env.transition_probability(state, action, next_state, r_id) * (reward + gamma * v[next_state]);
-                }
-            }
+    const GAMMA: f32 = 0.9;
+    const THETA: f32 = 1e-4;
 
-            if total > best_a_score {
-                best_a = action;
-                best_a_score = total;
+    /// Every backup scheme stops at a residual of θ(1 - γ)/2γ, so that each V is
+    /// within θ/2 of V* and any two of them within θ
+    fn assert_schemes_agree(env: &mut GridEnv) {
+        let s: Vec<usize> = (0..env.num_states()).collect();
+        let a: Vec<usize> = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let theta = THETA * (1.0 - GAMMA) / (2.0 * GAMMA);
+        let (reference, _, _) = value_iteration_with(&s, &a, &r, env, GAMMA, theta, BACKUP_SCHEMES[0]);
+        for scheme in &BACKUP_SCHEMES[1..] {
+            let (v, _, _) = value_iteration_with(&s, &a, &r, env, GAMMA, theta, *scheme);
+            for &state in &s {
+                assert!(
+                    (v[state] - reference[state]).abs() < THETA,
+                    "{:?}, state {}: {} vs {:?} {}",
+                    scheme,
+                    state,
+                    v[state],
+                    BACKUP_SCHEMES[0],
+                    reference[state]
+                );
             }
         }
+    }
 
-        pi[state] = best_a;
+    #[test]
+    fn backup_schemes_agree_on_grid_env() {
+        assert_schemes_agree(&mut GridEnv::new());
+    }
+
+    #[test]
+    fn backup_schemes_agree_on_dyna_maze() {
+        assert_schemes_agree(&mut GridEnv::dyna_maze(2));
     }
-    (v, pi)
 }
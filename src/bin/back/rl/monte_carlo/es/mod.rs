@@ -1,62 +1,76 @@
 extern crate rand;
 
 use crate::back::envs::basic_env::Env;
-use rand::Rng;
-use std::collections::HashMap;
+use crate::back::services::math::greedy_action;
+use kdam::tqdm;
+use nalgebra::DVector;
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
 
+/// First-visit Monte Carlo control with exploring starts.
+/// Every episode starts from a random non-terminal, non-blocked state (`Env::set_state`) with a random
+/// available action, then follows the greedy policy. Needs envs supporting `set_state`.
+/// Episodes are cut after `max_steps`, a greedy policy can loop forever (e.g. into a wall).
+/// Returns the greedy policy, Q and the return of every episode.
 pub fn monte_carlo_es(
     env: &mut dyn Env,
     num_episodes: usize,
     gamma: f32,
-) -> (Vec<usize>, Vec<Vec<f32>>) {
+    max_steps: usize,
+) -> (Vec<usize>, Vec<Vec<f32>>, Vec<f32>) {
     let num_states = env.num_states();
     let num_actions = env.num_actions();
 
     let mut q = vec![vec![0.0; num_actions]; num_states];
-    let mut returns: Vec<Vec<Vec<f32>>> = vec![vec![Vec::new(); num_actions]; num_states];
+    let mut n = vec![vec![0usize; num_actions]; num_states];
     let mut pi = vec![0; num_states];
+    let mut episode_returns = Vec::with_capacity(num_episodes);
+    let mut rng = rand::thread_rng();
 
-    for _ in 0..num_episodes {
+    for _ in tqdm!(0..num_episodes, position = 0) {
         env.reset();
-        let mut rng = rand::thread_rng();
-        let s0 = rng.gen_range(0..num_states);
-        let a0 = rng.gen_range(0..num_actions);
-
-        // env.set_state(s0);
-        env.step(a0 as i32);
-
-        let mut trajectory = vec![(s0, a0, 0.0)];
+        // Computed for every episode, as the walls of a grid may move during training
+        let excluded_states: Vec<usize> = env.get_terminal_states().into_iter().chain(env.get_blocked_states()).collect();
+        let start_states: Vec<usize> = (0..num_states).filter(|s| !excluded_states.contains(s)).collect();
+        let s0 = *start_states.choose(&mut rng).unwrap();
+        env.set_state(s0);
+        if env.is_game_over() {
+            continue;
+        }
+        let s0 = env.state_id();
+        let aa0 = env.available_actions();
+        let a0 = *aa0.as_slice().choose(&mut rng).unwrap();
 
+        let prev_score = env.score();
+        env.step(a0);
+        let r0 = env.score() - prev_score;
+        let mut trajectory: Vec<(usize, usize, f32, DVector<i32>)> = vec![(s0, a0 as usize, r0, aa0)];
 
-        while !env.is_game_over() {
+        while !env.is_game_over() && trajectory.len() < max_steps {
             let s = env.state_id();
-            let a = pi[s];
+            let aa = env.available_actions();
+            let a = greedy_action(&aa, &q[s]);
             let prev_score = env.score();
-            env.step(a as i32);
+            env.step(a);
             let r = env.score() - prev_score;
-            trajectory.push((s, a, r));
+            trajectory.push((s, a as usize, r, aa));
         }
 
-        let mut g = 0.0;
-        let mut visited = HashMap::new();
+        let mut seen = HashSet::new();
+        let first_visit: Vec<bool> = trajectory.iter().map(|(s, a, _, _)| seen.insert((*s, *a))).collect();
 
-        for (t, &(s_t, a_t, r_t)) in trajectory.iter().rev().enumerate() {
+        let mut g = 0.0;
+        for (t, (s_t, a_t, r_t, aa_t)) in trajectory.iter().enumerate().rev() {
             g = gamma * g + r_t;
 
-            if !visited.contains_key(&(s_t, a_t)) {
-                visited.insert((s_t, a_t), true);
-                returns[s_t][a_t].push(g);
-                q[s_t][a_t] = returns[s_t][a_t].iter().sum::<f32>() / returns[s_t][a_t].len() as f32;
-
-                pi[s_t] = q[s_t]
-                    .iter()
-                    .enumerate()
-                    .max_by(|(_, q1), (_, q2)| q1.partial_cmp(q2).unwrap())
-                    .unwrap()
-                    .0;
+            if first_visit[t] {
+                n[*s_t][*a_t] += 1;
+                q[*s_t][*a_t] += (g - q[*s_t][*a_t]) / n[*s_t][*a_t] as f32;
+                pi[*s_t] = greedy_action(aa_t, &q[*s_t]) as usize;
             }
         }
+        episode_returns.push(g);
     }
 
-    (pi, q)
+    (pi, q, episode_returns)
 }
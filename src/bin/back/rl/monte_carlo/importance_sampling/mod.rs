@@ -0,0 +1,167 @@
+use crate::back::envs::basic_env::Env;
+use crate::back::rl::monte_carlo::off_policy::{effective_sample_size, generate_episode, BehaviourPolicy};
+use crate::back::services::math::{policy_probability, RunningStats};
+use kdam::tqdm;
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+/// Off-policy estimators of V^pi, from Sutton & Barto chapter 5
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IsEstimator {
+    /// Average of ρ_{t:T-1} G_t
+    Ordinary,
+    /// Σ ρ_{t:T-1} G_t / Σ ρ_{t:T-1}
+    Weighted,
+    /// Each reward only weighted by the ratios of the decisions before it
+    PerDecision,
+    /// Discounting seen as a probability of termination, flat partial returns
+    /// only weighted by the ratios up to their horizon
+    DiscountingAwareOrdinary,
+    DiscountingAwareWeighted,
+}
+
+impl IsEstimator {
+    pub const ALL: [IsEstimator; 5] = [
+        IsEstimator::Ordinary,
+        IsEstimator::Weighted,
+        IsEstimator::PerDecision,
+        IsEstimator::DiscountingAwareOrdinary,
+        IsEstimator::DiscountingAwareWeighted,
+    ];
+}
+
+pub struct IsDiagnostics {
+    /// (Σw)² / Σw² over the episode weights ρ_{0:T-1}
+    pub ess: f32,
+    pub max_weight: f32,
+    /// Variance of the weighted returns averaged in each state
+    pub sample_variance: Vec<f32>,
+}
+
+/// Result of `compare_is_estimators` for one estimator, over independent runs
+#[derive(Debug)]
+pub struct EstimatorReport {
+    pub estimator: IsEstimator,
+    pub mean: f32,
+    pub variance: f32,
+    /// Within-run variance of the weighted returns from the start state, averaged
+    pub sample_variance: f32,
+    pub mean_ess: f32,
+    pub max_weight: f32,
+    pub num_runs: usize,
+}
+
+impl EstimatorReport {
+    /// Mean squared error over the runs: bias² + variance
+    pub fn mse(&self, truth: f32) -> f32 {
+        let n = self.num_runs as f32;
+        (self.mean - truth).powi(2) + self.variance * (n - 1.0) / n
+    }
+}
+
+/// Every-visit off-policy evaluation of `pi` from episodes of `behaviour`.
+/// An ε-greedy behaviour is taken around the most likely action of `pi`.
+pub fn off_policy_mc_evaluation(
+    env: &mut dyn Env,
+    pi: &[Vec<f32>],
+    behaviour: &BehaviourPolicy,
+    estimator: IsEstimator,
+    num_episodes: usize,
+    gamma: f32,
+    max_steps: usize,
+) -> (Vec<f32>, IsDiagnostics) {
+    let num_states = env.num_states();
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut v = vec![0.0f32; num_states];
+    let mut c = vec![0.0f32; num_states];
+    let mut stats = vec![RunningStats::default(); num_states];
+    let mut weights = Vec::with_capacity(num_episodes);
+
+    for _ in 0..num_episodes {
+        let trajectory = generate_episode(env, behaviour, pi, max_steps, &mut rng);
+
+        // Backward recursions, all starting from the end of the episode:
+        // g = G_t, w = ρ_{t:T-1}, g_pd = per-decision return,
+        // n_da / d_da = discounting-aware numerator / denominator
+        let (mut g, mut w, mut g_pd, mut n_da, mut d_da) = (0.0f32, 1.0f32, 0.0f32, 0.0f32, 1.0f32);
+
+        for (s_t, aa_t, a_t, r_t, b_t) in trajectory.iter().rev() {
+            let rho = policy_probability(&pi[*s_t], aa_t, *a_t) / b_t;
+
+            g = gamma * g + r_t;
+            w *= rho;
+            g_pd = rho * (r_t + gamma * g_pd);
+            let d = (1.0 - gamma) + gamma * d_da;
+            n_da = rho * (r_t * d + gamma * n_da);
+            d_da = rho * d;
+
+            let (numerator, denominator) = match estimator {
+                IsEstimator::Ordinary => (w * g, 1.0),
+                IsEstimator::Weighted => (w * g, w),
+                IsEstimator::PerDecision => (g_pd, 1.0),
+                IsEstimator::DiscountingAwareOrdinary => (n_da, 1.0),
+                IsEstimator::DiscountingAwareWeighted => (n_da, d_da),
+            };
+
+            // v = Σ numerator / Σ denominator, incrementally
+            c[*s_t] += denominator;
+            if c[*s_t] > 0.0 {
+                v[*s_t] += (numerator - denominator * v[*s_t]) / c[*s_t];
+            }
+            stats[*s_t].push(numerator);
+        }
+        weights.push(w);
+    }
+
+    let diagnostics = IsDiagnostics {
+        ess: effective_sample_size(&weights),
+        max_weight: weights.iter().cloned().fold(0.0, f32::max),
+        sample_variance: stats.iter().map(|s| s.variance()).collect(),
+    };
+    (v, diagnostics)
+}
+
+/// Runs every estimator `num_runs` times and reports the spread of their
+/// estimates of the start state value
+pub fn compare_is_estimators(
+    env: &mut dyn Env,
+    pi: &[Vec<f32>],
+    behaviour: &BehaviourPolicy,
+    num_episodes: usize,
+    num_runs: usize,
+    gamma: f32,
+    max_steps: usize,
+) -> Vec<EstimatorReport> {
+    env.reset();
+    let start = env.state_id();
+
+    let mut estimates = vec![RunningStats::default(); IsEstimator::ALL.len()];
+    let mut sample_variance = vec![0.0f32; IsEstimator::ALL.len()];
+    let mut ess = vec![0.0f32; IsEstimator::ALL.len()];
+    let mut max_weight = vec![0.0f32; IsEstimator::ALL.len()];
+
+    for _ in tqdm!(0..num_runs, position = 0) {
+        for (i, &estimator) in IsEstimator::ALL.iter().enumerate() {
+            let (v, diagnostics) =
+                off_policy_mc_evaluation(env, pi, behaviour, estimator, num_episodes, gamma, max_steps);
+            estimates[i].push(v[start]);
+            sample_variance[i] += diagnostics.sample_variance[start];
+            ess[i] += diagnostics.ess;
+            max_weight[i] = max_weight[i].max(diagnostics.max_weight);
+        }
+    }
+
+    IsEstimator::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, &estimator)| EstimatorReport {
+            estimator,
+            mean: estimates[i].mean,
+            variance: estimates[i].variance(),
+            sample_variance: sample_variance[i] / num_runs as f32,
+            mean_ess: ess[i] / num_runs as f32,
+            max_weight: max_weight[i],
+            num_runs,
+        })
+        .collect()
+}
@@ -1,3 +1,5 @@
 pub mod on_policy;
 pub mod off_policy;
 pub mod es;
+pub mod prediction;
+pub mod importance_sampling;
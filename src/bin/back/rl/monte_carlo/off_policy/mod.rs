@@ -1,185 +1,198 @@
 extern crate rand;
 
 use crate::back::envs::basic_env::Env;
-use rand::distributions::{WeightedIndex, Distribution, Uniform};
+use crate::back::services::math::{epsilon_greedy_probabilities, greedy_action, policy_probability, sample_action};
 use kdam::tqdm;
+use nalgebra::DVector;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportanceSampling {
+    /// Plain average of the weighted returns, unbiased but high variance
+    Ordinary,
+    /// Average normalised by the sum of the weights, biased but much lower variance
+    Weighted,
+}
 
+/// Policy used to generate the episodes. It has to give a non-zero probability
+/// to every action the target policy can take.
+#[derive(Clone, Debug)]
+pub enum BehaviourPolicy {
+    Uniform,
+    /// ε-greedy with respect to the current estimate of Q
+    EpsilonGreedy(f32),
+    /// Fixed soft policy `b[s][a]`, renormalised over the available actions
+    Fixed(Vec<Vec<f32>>),
+}
 
-pub fn off_policy_mc_control(
-    env: &mut dyn Env,
-    num_episodes: usize,
-    gamma: f32,
-) -> (Vec<usize>, Vec<Vec<f32>>) {
-    let num_states = env.num_states();
-    let num_actions = env.num_actions();
-
-    // Initialize Q and C
-    let mut q = vec![vec![0.0; num_actions]; num_states];
-    let mut c = vec![vec![0.0; num_actions]; num_states];
-    let mut pi = vec![0; num_states];
+impl BehaviourPolicy {
+    /// Fixed policy with random (strictly positive) probabilities, drawn once
+    pub fn random(num_states: usize, num_actions: usize, rng: &mut impl Rng) -> Self {
+        let b = (0..num_states)
+            .map(|_| {
+                let row: Vec<f32> = (0..num_actions).map(|_| rng.gen_range(0.1..1.0)).collect();
+                let sum: f32 = row.iter().sum();
+                row.iter().map(|p| p / sum).collect()
+            })
+            .collect();
+        BehaviourPolicy::Fixed(b)
+    }
 
-    for _ in tqdm!(0..num_episodes, position = 0) {
-        let b = vec![vec![1.0 / num_actions as f32; num_actions]; num_states];
-        let mut trajectory = Vec::new();
-        let all_actions: Vec<usize> = (0..num_actions).collect();
-        env.reset();
-
-        // Generate an episode following behavior policy b
-        while !env.is_game_over() {
-            let s = env.state_id();
-            let pi_s = &b[s];
-            let dist = WeightedIndex::new(pi_s).unwrap();
-            let mut rng = rand::thread_rng();
-            let a: usize = all_actions[dist.sample(&mut rng)];
-
-            let prev_score = env.score();
-            env.step(a as i32);
-            let r = env.score() - prev_score;
-            trajectory.push((s, a, r));
+    /// Action probabilities in `s`, indexed by action id
+    fn probabilities(&self, s: usize, q_s: &[f32], aa: &DVector<i32>) -> Vec<f32> {
+        match self {
+            BehaviourPolicy::Uniform => vec![1.0; q_s.len()],
+            BehaviourPolicy::EpsilonGreedy(epsilon) => epsilon_greedy_probabilities(aa, q_s, *epsilon),
+            BehaviourPolicy::Fixed(b) => b[s].clone(),
         }
+    }
+}
 
-        let mut g = 0.0;
-        let mut w = 1.0;
-
-        // Backward pass through the trajectory
-        for &(s_t, a_t, r_t) in trajectory.iter().rev() {
-            g = gamma * g + r_t;
-
-            c[s_t][a_t] += w;
-            q[s_t][a_t] += w / c[s_t][a_t] * (g - q[s_t][a_t]);
-
-            pi[s_t] = q[s_t].iter().enumerate().max_by(|x, y| x.1.partial_cmp(y.1).unwrap()).unwrap().0;
+/// Effective sample size of the episode weights: (Σw)² / Σw²
+pub fn effective_sample_size(weights: &[f32]) -> f32 {
+    let sum: f32 = weights.iter().sum();
+    let sum_sq: f32 = weights.iter().map(|w| w * w).sum();
+    if sum_sq == 0.0 {
+        0.0
+    } else {
+        sum * sum / sum_sq
+    }
+}
 
-            if a_t != pi[s_t] {
-                break;
+/// Add the return `g` with importance weight `w` to the estimate `x`,
+/// `c` being the number of returns (ordinary) or the sum of the weights (weighted)
+fn update(x: &mut f32, c: &mut f32, w: f32, g: f32, sampling: ImportanceSampling) {
+    match sampling {
+        ImportanceSampling::Ordinary => {
+            *c += 1.0;
+            *x += (w * g - *x) / *c;
+        }
+        ImportanceSampling::Weighted => {
+            *c += w;
+            if *c > 0.0 {
+                *x += w / *c * (g - *x);
             }
-            w *= 1.0 / b[s_t].iter().sum::<f32>();
         }
     }
-    (pi, q)
 }
 
-pub fn off_policy_mc_control_dynamic(
+/// Episode generated by `behaviour`, as (state, available actions, action, reward, b(a|s))
+pub fn generate_episode(
     env: &mut dyn Env,
+    behaviour: &BehaviourPolicy,
+    q: &[Vec<f32>],
+    max_steps: usize,
+    rng: &mut impl Rng,
+) -> Vec<(usize, DVector<i32>, i32, f32, f32)> {
+    let mut trajectory = Vec::new();
+    env.reset();
+
+    while !env.is_game_over() && trajectory.len() < max_steps {
+        let s = env.state_id();
+        let aa = env.available_actions();
+        let b_s = behaviour.probabilities(s, &q[s], &aa);
+        let a = sample_action(&b_s, &aa, rng);
+        let b = policy_probability(&b_s, &aa, a);
+
+        let prev_score = env.score();
+        env.step(a);
+        let r = env.score() - prev_score;
+        trajectory.push((s, aa, a, r, b));
+    }
+    trajectory
+}
+
+/// Off-policy Monte Carlo control of the greedy policy.
+/// Returns the policy, Q and the effective sample size of the episode weights.
+pub fn off_policy_mc_control(
+    env: &mut dyn Env,
+    behaviour: &BehaviourPolicy,
+    sampling: ImportanceSampling,
     num_episodes: usize,
     gamma: f32,
-    action_spaces: &[usize],
-) -> (Vec<usize>, Vec<Vec<f32>>) {
+    max_steps: usize,
+) -> (Vec<usize>, Vec<Vec<f32>>, f32) {
     let num_states = env.num_states();
+    let num_actions = env.num_actions();
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
 
-    // Initialize Q and C
-    let mut q: Vec<Vec<f32>> = action_spaces
-        .iter()
-        .map(|&num_actions| vec![rand::random::<f32>(); num_actions])
-        .collect();
-    let mut c: Vec<Vec<f32>> = action_spaces
-        .iter()
-        .map(|&num_actions| vec![0.0; num_actions])
-        .collect();
-    let mut pi = vec![0; num_states]; // Each state maps to one action
-
+    let mut q = vec![vec![0.0; num_actions]; num_states];
+    let mut c = vec![vec![0.0; num_actions]; num_states];
+    let mut pi = vec![0; num_states];
+    let mut weights = Vec::with_capacity(num_episodes);
 
     for _ in tqdm!(0..num_episodes, position = 0) {
-        let b: Vec<Vec<f32>> = action_spaces
-            .iter()
-            .map(|&num_actions| {
-                let mut actions: Vec<f32> = (0..num_actions).map(|_| rand::random::<f32>()).collect();
-                let sum: f32 = actions.iter().sum();
-                actions.iter_mut().for_each(|p| *p /= sum); // Normalize to sum to 1
-                actions
-            })
-            .collect();
-
-        let mut trajectory = Vec::new();
-        env.reset();
-
-        while !env.is_game_over() {
-            let s = env.state_id();
-            let pi_s = &b[s];
-            let dist = WeightedIndex::new(pi_s).unwrap();
-            let mut rng = rand::thread_rng();
-
-            let available_actions = env.available_actions();
-
-            let action_index = dist.sample(&mut rng);
-            let a = available_actions[action_index] as usize; // Sample action for state `s`
-            let prev_score = env.score();
-            env.step(a as i32);
-            let r = env.score() - prev_score;
-            trajectory.push((s, action_index, r));
-        }
+        let trajectory = generate_episode(env, behaviour, &q, max_steps, &mut rng);
 
         let mut g = 0.0;
         let mut w = 1.0;
 
         // Backward pass through the trajectory
-        for &(s_t, a_t, r_t) in trajectory.iter().rev() {
+        for (s_t, aa_t, a_t, r_t, b_t) in trajectory.iter().rev() {
+            let (s_t, a_t) = (*s_t, *a_t as usize);
             g = gamma * g + r_t;
 
-            c[s_t][a_t] += w;
-            q[s_t][a_t] += w / c[s_t][a_t] * (g - q[s_t][a_t]);
-
-            pi[s_t] = q[s_t].iter().enumerate().max_by(|x, y| x.1.partial_cmp(y.1).unwrap()).unwrap().0;
+            update(&mut q[s_t][a_t], &mut c[s_t][a_t], w, g, sampling);
+            pi[s_t] = greedy_action(aa_t, &q[s_t]) as usize;
 
             if a_t != pi[s_t] {
-                break;
+                // π(a|s) = 0: the earlier returns weigh nothing, but still
+                // count in the ordinary average
+                w = 0.0;
+                if sampling == ImportanceSampling::Weighted {
+                    break;
+                }
+            } else {
+                w /= b_t;
             }
-            w *= 1.0 / b[s_t].iter().sum::<f32>();
         }
+        weights.push(w);
     }
-    (pi, q)
+    (pi, q, effective_sample_size(&weights))
 }
 
-pub fn off_policy_mc_control_secret(
+/// Off-policy Monte Carlo prediction of V^pi and Q^pi from episodes of `behaviour`.
+/// Returns V, Q and the effective sample size of the episode weights.
+pub fn off_policy_mc_prediction(
     env: &mut dyn Env,
+    pi: &[Vec<f32>],
+    behaviour: &BehaviourPolicy,
+    sampling: ImportanceSampling,
     num_episodes: usize,
     gamma: f32,
-) -> (Vec<usize>, Vec<Vec<f32>>) {
+    max_steps: usize,
+) -> (Vec<f32>, Vec<Vec<f32>>, f32) {
     let num_states = env.num_states();
     let num_actions = env.num_actions();
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
 
+    let mut v = vec![0.0; num_states];
+    let mut c_v = vec![0.0; num_states];
     let mut q = vec![vec![0.0; num_actions]; num_states];
-    let mut c = vec![vec![0.0; num_actions]; num_states];
-    let mut pi = vec![0; num_states];
-    for _ in tqdm!(0..num_episodes, position = 0) {
-        let b = vec![vec![1.0 / num_actions as f32; num_actions]; num_states];
-
-        let mut trajectory = Vec::new();
-        env.reset();
+    let mut c_q = vec![vec![0.0; num_actions]; num_states];
+    let mut weights = Vec::with_capacity(num_episodes);
 
-        while !env.is_game_over() {
-            let s = env.state_id();
-            let pi_s = &b[s];
-            let mut rng = rand::thread_rng();
-
-            let available_actions = env.available_actions();
-
-            let dist = Uniform::from(0..available_actions.len());
-            let action_index = dist.sample(&mut rng);
-            let a = available_actions[action_index] as usize; // Sample action for state `s`
-            let prev_score = env.score();
-            env.step(a as i32);
-            let r = env.score() - prev_score;
-            trajectory.push((s, action_index, r));
-        }
+    for _ in tqdm!(0..num_episodes, position = 0) {
+        let trajectory = generate_episode(env, behaviour, &q, max_steps, &mut rng);
 
         let mut g = 0.0;
         let mut w = 1.0;
 
-        // Backward pass through the trajectory
-        for &(s_t, a_t, r_t) in trajectory.iter().rev() {
+        for (s_t, aa_t, a_t, r_t, b_t) in trajectory.iter().rev() {
+            let (s_t, a_t) = (*s_t, *a_t as usize);
             g = gamma * g + r_t;
 
-            c[s_t][a_t] += w;
-            q[s_t][a_t] += w / c[s_t][a_t] * (g - q[s_t][a_t]);
-
-            pi[s_t] = q[s_t].iter().enumerate().max_by(|x, y| x.1.partial_cmp(y.1).unwrap()).unwrap().0;
+            // Q(s, a) is conditioned on a, so its weight stops at t + 1
+            update(&mut q[s_t][a_t], &mut c_q[s_t][a_t], w, g, sampling);
+            w *= policy_probability(&pi[s_t], aa_t, a_t as i32) / b_t;
+            update(&mut v[s_t], &mut c_v[s_t], w, g, sampling);
 
-            if a_t != pi[s_t] {
+            if w == 0.0 && sampling == ImportanceSampling::Weighted {
                 break;
             }
-            w *= 1.0 / b[s_t].iter().sum::<f32>();
         }
+        weights.push(w);
     }
-    (pi, q)
+    (v, q, effective_sample_size(&weights))
 }
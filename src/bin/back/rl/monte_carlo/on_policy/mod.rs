@@ -18,7 +18,7 @@ pub fn on_policy_first_visit_monte_carlo_control(
 
     // let mut pi = vec![vec![1.0 / num_actions as f32; num_actions]; num_states];
     let mut q = vec![vec![rand::random::<f32>(); num_actions]; num_states];
-    let mut returns: Vec<Vec<Vec<f32>>> = vec![vec![Vec::new(); num_actions]; num_states];
+    let mut n = vec![vec![0usize; num_actions]; num_states];
     let all_actions: Vec<usize> = (0..num_actions).collect();
 
     for _ in tqdm!(0..num_episodes, position = 0) {
@@ -45,9 +45,9 @@ pub fn on_policy_first_visit_monte_carlo_control(
             g = gamma * g + *r_t;
             if !visited.contains_key(&(*s_t, *a_t)) {
                 visited.insert((*s_t, *a_t), true);
-                returns[*s_t][*a_t].push(g);
+                n[*s_t][*a_t] += 1;
 
-                q[*s_t][*a_t] = returns[*s_t][*a_t].iter().sum::<f32>() / returns[*s_t][*a_t].len() as f32;
+                q[*s_t][*a_t] += (g - q[*s_t][*a_t]) / n[*s_t][*a_t] as f32;
 
                 let best_a = q[*s_t].iter().enumerate().max_by(|x, y| x.1.partial_cmp(y.1).unwrap()).unwrap().0;
 
@@ -81,14 +81,14 @@ pub fn on_policy_first_visit_monte_carlo_control_dynamic(
         })
         .collect();
 
-    // Initialize Q-values and returns based on action spaces
+    // Initialize Q-values and visit counts based on action spaces
     let mut q: Vec<Vec<f32>> = action_spaces
         .iter()
         .map(|&num_actions| vec![rand::random::<f32>(); num_actions])
         .collect();
-    let mut returns: Vec<Vec<Vec<f32>>> = action_spaces
+    let mut n: Vec<Vec<usize>> = action_spaces
         .iter()
-        .map(|&num_actions| vec![Vec::new(); num_actions])
+        .map(|&num_actions| vec![0; num_actions])
         .collect();
 
     for _ in tqdm!(0..num_episodes, position = 0) {
@@ -120,8 +120,8 @@ pub fn on_policy_first_visit_monte_carlo_control_dynamic(
             if !visited.contains_key(&(*s_t, *a_t)) {
                 visited.insert((*s_t, *a_t), true);
 
-                returns[*s_t][*a_t].push(g);
-                q[*s_t][*a_t] = returns[*s_t][*a_t].iter().sum::<f32>() / returns[*s_t][*a_t].len() as f32;
+                n[*s_t][*a_t] += 1;
+                q[*s_t][*a_t] += (g - q[*s_t][*a_t]) / n[*s_t][*a_t] as f32;
 
                 let best_a = q[*s_t]
                     .iter()
@@ -155,9 +155,9 @@ pub fn on_policy_first_visit_monte_carlo_control_secret(
     let num_actions = env.num_actions();
     let mut pi: Vec<Vec<f32>> = vec![vec![1.0 / num_actions as f32; num_actions]; num_states];
 
-    // Initialize Q-values and returns based on action spaces
+    // Initialize Q-values and visit counts based on action spaces
     let mut q = vec![vec![rand::random::<f32>(); num_actions]; num_states];
-    let mut returns: Vec<Vec<Vec<f32>>> = vec![vec![Vec::new(); num_actions]; num_states];
+    let mut n = vec![vec![0usize; num_actions]; num_states];
 
     for _ in tqdm!(0..num_episodes, position = 0) {
         let mut trajectory = Vec::new();
@@ -188,8 +188,8 @@ pub fn on_policy_first_visit_monte_carlo_control_secret(
             if !visited.contains_key(&(*s_t, *a_t)) {
                 visited.insert((*s_t, *a_t), true);
 
-                returns[*s_t][*a_t].push(g);
-                q[*s_t][*a_t] = returns[*s_t][*a_t].iter().sum::<f32>() / returns[*s_t][*a_t].len() as f32;
+                n[*s_t][*a_t] += 1;
+                q[*s_t][*a_t] += (g - q[*s_t][*a_t]) / n[*s_t][*a_t] as f32;
 
                 let best_a = q[*s_t]
                     .iter()
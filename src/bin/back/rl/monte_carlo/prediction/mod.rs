@@ -0,0 +1,70 @@
+use crate::back::envs::basic_env::Env;
+use crate::back::services::math::sample_action;
+use kdam::tqdm;
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VisitMode {
+    FirstVisit,
+    EveryVisit,
+}
+
+/// Monte Carlo prediction of V^pi and Q^pi, the model-free counterpart of
+/// `iterative_policy_evaluation`. `pi[s][a]` is renormalised over the available actions.
+/// Returns are averaged incrementally, episodes are cut after `max_steps`.
+pub fn monte_carlo_prediction(
+    env: &mut dyn Env,
+    pi: &[Vec<f32>],
+    num_episodes: usize,
+    gamma: f32,
+    mode: VisitMode,
+    max_steps: usize,
+) -> (Vec<f32>, Vec<Vec<f32>>) {
+    let num_states = env.num_states();
+    let num_actions = env.num_actions();
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+
+    let mut v = vec![0.0f32; num_states];
+    let mut n_v = vec![0usize; num_states];
+    let mut q = vec![vec![0.0f32; num_actions]; num_states];
+    let mut n_q = vec![vec![0usize; num_actions]; num_states];
+
+    for _ in tqdm!(0..num_episodes, position = 0) {
+        env.reset();
+        let mut trajectory = Vec::new();
+
+        while !env.is_game_over() && trajectory.len() < max_steps {
+            let s = env.state_id();
+            let a = sample_action(&pi[s], &env.available_actions(), &mut rng);
+            let prev_score = env.score();
+            env.step(a);
+            let r = env.score() - prev_score;
+            trajectory.push((s, a as usize, r));
+        }
+
+        let (first_s, first_sa): (Vec<bool>, Vec<bool>) = if mode == VisitMode::FirstVisit {
+            let mut seen_s = HashSet::new();
+            let mut seen_sa = HashSet::new();
+            trajectory.iter().map(|&(s, a, _)| (seen_s.insert(s), seen_sa.insert((s, a)))).unzip()
+        } else {
+            (vec![true; trajectory.len()], vec![true; trajectory.len()])
+        };
+
+        let mut g = 0.0;
+        for (t, &(s_t, a_t, r_t)) in trajectory.iter().enumerate().rev() {
+            g = gamma * g + r_t;
+
+            if first_s[t] {
+                n_v[s_t] += 1;
+                v[s_t] += (g - v[s_t]) / n_v[s_t] as f32;
+            }
+            if first_sa[t] {
+                n_q[s_t][a_t] += 1;
+                q[s_t][a_t] += (g - q[s_t][a_t]) / n_q[s_t][a_t] as f32;
+            }
+        }
+    }
+    (v, q)
+}
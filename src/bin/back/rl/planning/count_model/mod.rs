@@ -0,0 +1,187 @@
+use kdam::tqdm;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::back::envs::basic_env::Env;
+use crate::back::envs::tabular_mdp::TabularMdp;
+use crate::back::rl::dynamic_programming::value_iteration::value_iteration;
+use crate::back::rl::planning::dyna_q::{max_available_q, AvailableActions};
+use crate::back::services::math::greedy_action_random_ties;
+
+/// (S', reward index, count)
+type Outcome = (usize, usize, u32);
+
+/// Empirical model of a stochastic env: how many times each (S', R) followed
+/// each (S, A). P̂(s', r | s, a) is the fraction of the visits of (s, a).
+pub struct CountModel {
+    num_actions: usize,
+    /// Distinct rewards seen, the outcomes refer to them by index
+    rewards: Vec<f32>,
+    outcomes: HashMap<(usize, i32), Vec<Outcome>>,
+    /// Keys of `outcomes`, to sample them in constant time
+    pairs: Vec<(usize, i32)>,
+    terminals: HashSet<usize>,
+    /// Number of episodes started in each state
+    starts: HashMap<usize, u32>,
+}
+
+impl CountModel {
+    pub fn new(env: &dyn Env) -> Self {
+        CountModel {
+            num_actions: env.num_actions(),
+            rewards: Vec::new(),
+            outcomes: HashMap::new(),
+            pairs: Vec::new(),
+            terminals: HashSet::new(),
+            starts: HashMap::new(),
+        }
+    }
+
+    pub fn observe_start(&mut self, s: usize) {
+        *self.starts.entry(s).or_insert(0) += 1;
+    }
+
+    pub fn update(&mut self, s: usize, a: i32, s_p: usize, r: f32, done: bool) {
+        let r_index = match self.rewards.iter().position(|&reward| reward == r) {
+            Some(index) => index,
+            None => {
+                self.rewards.push(r);
+                self.rewards.len() - 1
+            }
+        };
+        if done {
+            self.terminals.insert(s_p);
+        }
+        let outcomes = self.outcomes.entry((s, a)).or_insert_with(|| {
+            self.pairs.push((s, a));
+            Vec::new()
+        });
+        match outcomes.iter_mut().find(|(next, r, _)| *next == s_p && *r == r_index) {
+            Some((_, _, count)) => *count += 1,
+            None => outcomes.push((s_p, r_index, 1)),
+        }
+    }
+
+    /// A pair (S, A) already tried, uniformly at random
+    pub fn sample_pair(&self, rng: &mut impl Rng) -> Option<(usize, i32)> {
+        self.pairs.choose(rng).copied()
+    }
+
+    /// (S', R, whether S' is terminal) drawn from P̂(., . | s, a)
+    pub fn sample(&self, s: usize, a: i32, rng: &mut impl Rng) -> (usize, f32, bool) {
+        let outcomes = &self.outcomes[&(s, a)];
+        let total: u32 = outcomes.iter().map(|&(_, _, count)| count).sum();
+        let mut k = rng.gen_range(0..total);
+        for &(s_p, r_index, count) in outcomes {
+            if k < count {
+                return (s_p, self.rewards[r_index], self.terminals.contains(&s_p));
+            }
+            k -= count;
+        }
+        unreachable!()
+    }
+
+    /// The model as an MDP over the states seen so far, starting in the most
+    /// frequent start state. Also returns the id in the env of each of its states.
+    pub fn to_mdp(&self) -> (TabularMdp, Vec<usize>) {
+        let mut seen = BTreeSet::new();
+        seen.extend(self.starts.keys());
+        for (&(s, _), outcomes) in &self.outcomes {
+            seen.insert(s);
+            seen.extend(outcomes.iter().map(|&(s_p, _, _)| s_p));
+        }
+        let states: Vec<usize> = seen.into_iter().collect();
+        let index: HashMap<usize, usize> = states.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+
+        let mut transitions = vec![vec![Vec::new(); self.num_actions]; states.len()];
+        for (&(s, a), outcomes) in &self.outcomes {
+            let total: u32 = outcomes.iter().map(|&(_, _, count)| count).sum();
+            transitions[index[&s]][a as usize] = outcomes
+                .iter()
+                .map(|&(s_p, r_index, count)| (index[&s_p], r_index, count as f32 / total as f32))
+                .collect();
+        }
+        let terminals = self.terminals.iter().map(|s| index[s]).collect();
+        let start = self.starts.iter().max_by_key(|&(_, &count)| count).map(|(s, _)| index[s]).unwrap_or(0);
+
+        (TabularMdp::new(self.num_actions, self.rewards.clone(), transitions, terminals, start), states)
+    }
+}
+
+/// Dyna-Q with a count-based model: planning replays transitions drawn from the
+/// empirical distribution of (S', R) instead of the last one observed, which
+/// suits stochastic envs
+pub fn stochastic_dyna_q(
+    env: &mut dyn Env,
+    max_episodes: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+) -> (Vec<Vec<f32>>, CountModel) {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut q = vec![vec![0.0f32; env.num_actions()]; env.num_states()];
+    let mut model = CountModel::new(env);
+    let mut available: AvailableActions = HashMap::new();
+
+    for _ in tqdm!(0..max_episodes, position = 0) {
+        env.reset();
+        let mut s = env.state_id();
+        model.observe_start(s);
+
+        while !env.is_game_over() {
+            let aa = env.available_actions();
+            let a = if rng.gen::<f32>() < epsilon {
+                *aa.as_slice().choose(&mut rng).unwrap()
+            } else {
+                greedy_action_random_ties(&aa, &q[s], &mut rng)
+            };
+
+            let prev_score = env.score();
+            env.step(a);
+            let r = env.score() - prev_score;
+            let s_p = env.state_id();
+            let done = env.is_game_over();
+
+            if !done {
+                available.insert(s_p, env.available_actions());
+            }
+            let max_q_s_p = max_available_q(&q, &available, s_p, done);
+            q[s][a as usize] += alpha * (r + gamma * max_q_s_p - q[s][a as usize]);
+            model.update(s, a, s_p, r, done);
+
+            for _ in 0..planning_steps {
+                let (s_plan, a_plan) = model.sample_pair(&mut rng).unwrap();
+                let (s_plan_p, r_plan, done_plan) = model.sample(s_plan, a_plan, &mut rng);
+                let max_q_s_plan_p = max_available_q(&q, &available, s_plan_p, done_plan);
+                q[s_plan][a_plan as usize] += alpha * (r_plan + gamma * max_q_s_plan_p - q[s_plan][a_plan as usize]);
+            }
+
+            s = s_p;
+        }
+    }
+    (q, model)
+}
+
+/// Certainty-equivalence planning: value iteration on the learned model, as if
+/// it were the true env. The pairs never tried are worth 0 to value iteration.
+/// Returns V and the greedy policy, indexed by the state ids of the env.
+pub fn certainty_equivalence(model: &CountModel, num_states: usize, gamma: f32, theta: f32) -> (Vec<f32>, Vec<usize>) {
+    let (mut mdp, states) = model.to_mdp();
+    let s: Vec<usize> = (0..mdp.num_states()).collect();
+    let a: Vec<usize> = (0..mdp.num_actions()).collect();
+    let r = mdp.get_reward_vector();
+    let (v_mdp, pi_mdp) = value_iteration(&s, &a, &r, &mut mdp, gamma, theta);
+
+    let mut v = vec![0.0f32; num_states];
+    let mut pi = vec![0usize; num_states];
+    for (i, &state) in states.iter().enumerate() {
+        v[state] = v_mdp[i];
+        pi[state] = pi_mdp[i];
+    }
+    (v, pi)
+}
@@ -1,13 +1,98 @@
 use kdam::tqdm;
 
-use rand::seq::IteratorRandom;
-use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use rand_xoshiro::Xoshiro256PlusPlus;
 
+use nalgebra::DVector;
+
 use std::collections::HashMap;
 
 use crate::back::envs::basic_env::Env;
-use crate::back::services::math::{max, epsilon_greedy_action};
+use crate::back::envs::grid_world_env::GridEnv;
+use crate::back::services::math::{greedy_action, greedy_action_random_ties};
+
+/// Deterministic model: (S, A) -> (S', R, whether S' is terminal)
+pub type Model = HashMap<(usize, i32), (usize, f32, bool)>;
+
+/// Actions seen available in each state, so that the targets computed on a
+/// model only maximise over those
+pub type AvailableActions = HashMap<usize, DVector<i32>>;
+
+/// max Q(s', a') over the actions seen available in s', 0 when s' is terminal
+pub fn max_available_q(q: &[Vec<f32>], available: &AvailableActions, s_p: usize, done: bool) -> f32 {
+    if done {
+        return 0.0;
+    }
+    let aa = &available[&s_p];
+    q[s_p][greedy_action(aa, &q[s_p]) as usize]
+}
+
+/// Where the planning updates of Dyna-Q are taken
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlanningDistribution {
+    /// Pairs drawn uniformly among those in the model
+    Uniform,
+    /// Pairs met along ε-greedy trajectories simulated in the model from the
+    /// current state (trajectory sampling, Sutton & Barto, section 8.6)
+    OnPolicy,
+}
+
+/// Everything Dyna-Q learns during a run, kept between episodes
+pub struct DynaQState {
+    pub q: Vec<Vec<f32>>,
+    pub model: Model,
+    available: AvailableActions,
+    /// Keys of the model, to sample them in constant time
+    pairs: Vec<(usize, i32)>,
+    /// Real step at which each (S, A) was last tried, for the Dyna-Q+ bonus
+    last_visit: HashMap<(usize, i32), usize>,
+    /// Number of real steps played
+    pub steps: usize,
+    distribution: PlanningDistribution,
+}
+
+impl DynaQState {
+    pub fn new(env: &dyn Env) -> Self {
+        Self::with_distribution(env, PlanningDistribution::Uniform)
+    }
+
+    pub fn with_distribution(env: &dyn Env, distribution: PlanningDistribution) -> Self {
+        DynaQState {
+            q: vec![vec![0.0f32; env.num_actions()]; env.num_states()],
+            model: HashMap::new(),
+            available: HashMap::new(),
+            pairs: Vec::new(),
+            last_visit: HashMap::new(),
+            steps: 0,
+            distribution,
+        }
+    }
+
+    fn update_model(&mut self, s: usize, a: i32, transition: (usize, f32, bool)) {
+        if self.model.insert((s, a), transition).is_none() {
+            self.pairs.push((s, a));
+        }
+    }
+
+    /// Q-learning update of (s, a) on the transition of the model, with the
+    /// Dyna-Q+ bonus when `kappa` > 0. Returns the S' of the model and whether it is terminal.
+    fn plan(&mut self, s: usize, a: i32, alpha: f32, gamma: f32, kappa: f32) -> (usize, bool) {
+        let (s_p, r, done) = self.model[&(s, a)];
+
+        let bonus = if kappa > 0.0 {
+            let tau = self.steps - self.last_visit.get(&(s, a)).copied().unwrap_or(0);
+            kappa * (tau as f32).sqrt()
+        } else {
+            0.0
+        };
+
+        // Update Q(S, A) based on the simulated experience, S' being terminal in the model
+        let max_q_s_p = max_available_q(&self.q, &self.available, s_p, done);
+        self.q[s][a as usize] += alpha * (r + bonus + gamma * max_q_s_p - self.q[s][a as usize]);
+        (s_p, done)
+    }
+}
 
 pub fn dyna_q(
     env: &mut dyn Env,
@@ -16,69 +101,190 @@ pub fn dyna_q(
     epsilon: f32,
     gamma: f32,
     planning_steps: usize,
-) -> (Vec<Vec<f32>>, HashMap<(usize, i32), (usize, f32)>) {
+) -> (Vec<Vec<f32>>, Model) {
+    let state = DynaQState::new(env);
+    dyna(env, state, max_episodes, alpha, epsilon, gamma, planning_steps, 0.0)
+}
+
+/// Dyna-Q whose planning follows simulated ε-greedy trajectories from the current
+/// state instead of drawing pairs uniformly, so that the updates go to the states
+/// the agent is likely to meet
+pub fn dyna_q_trajectory_sampling(
+    env: &mut dyn Env,
+    max_episodes: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+) -> (Vec<Vec<f32>>, Model) {
+    let state = DynaQState::with_distribution(env, PlanningDistribution::OnPolicy);
+    dyna(env, state, max_episodes, alpha, epsilon, gamma, planning_steps, 0.0)
+}
+
+/// Dyna-Q+: planning adds a bonus κ√τ to the reward of a pair not tried for τ
+/// real steps, so that the agent goes back to check the parts of the model that
+/// may be out of date. Actions never tried from a visited state are planned as
+/// leading back to the same state with no reward.
+pub fn dyna_q_plus(
+    env: &mut dyn Env,
+    max_episodes: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+    kappa: f32,
+) -> (Vec<Vec<f32>>, Model) {
+    let state = DynaQState::new(env);
+    dyna(env, state, max_episodes, alpha, epsilon, gamma, planning_steps, kappa)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn dyna(
+    env: &mut dyn Env,
+    mut state: DynaQState,
+    max_episodes: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+    kappa: f32,
+) -> (Vec<Vec<f32>>, Model) {
     let mut rng = Xoshiro256PlusPlus::from_entropy();
 
-    // Initialize Q-table
-    let mut q = Vec::new();
-    for _ in 0..env.num_states() {
-        let mut v = Vec::new();
-        for _ in 0..env.num_actions() {
-            v.push(0.0f32);
-        }
-        q.push(v);
+    for _ in tqdm!(0..max_episodes, position = 0) {
+        dyna_q_episode(env, &mut state, alpha, epsilon, gamma, planning_steps, kappa, usize::MAX, &mut rng);
     }
 
-    // Initialize the model as a HashMap
-    let mut model: HashMap<(usize, i32), (usize, f32)> = HashMap::new();
+    (state.q, state.model)
+}
 
-    for _ in tqdm!(0..max_episodes, position = 0) {
-        env.reset();
-        let mut s = env.state_id();
-        let mut aa = env.available_actions();
-
-        while !env.is_game_over() {
-            // Step (b): Choose action A using epsilon-greedy
-            let a = epsilon_greedy_action(aa.clone(), &q, s, epsilon, &mut rng);
-
-            let prev_score = env.score();
-            env.step(a);
-            let r = env.score() - prev_score;
-            let s_p = env.state_id();
-            let aa_p = env.available_actions();
-
-            // Step (d): Update Q(S, A)
-            let max_q_s_p = if env.is_game_over() {
-                0.0f32
-            } else {
-                max(&q[s_p])
-            };
-            q[s][a as usize] += alpha * (r + gamma * max_q_s_p - q[s][a as usize]);
-
-            // Step (e): Update the model
-            model.insert((s, a), (s_p, r));
-
-            // Step (f): Perform planning
-            for _ in 0..planning_steps {
-                // Randomly sample a previously observed state-action pair
-                let &(s_rand, a_rand) = model.keys().choose(&mut rng).unwrap();
-                let &(s_rand_p, r_rand) = model.get(&(s_rand, a_rand)).unwrap();
-
-                // Update Q(S, A) based on the simulated experience
-                let max_q_rand_s_p = if env.is_game_over() {
-                    0.0f32
-                } else {
-                    max(&q[s_rand_p])
-                };
-                q[s_rand][a_rand as usize] +=
-                    alpha * (r_rand + gamma * max_q_rand_s_p - q[s_rand][a_rand as usize]);
+/// Plays one episode of at most `max_steps` real steps, planning after each of
+/// them, and returns the reward of each real step. `kappa` = 0 is Dyna-Q.
+#[allow(clippy::too_many_arguments)]
+pub fn dyna_q_episode(
+    env: &mut dyn Env,
+    state: &mut DynaQState,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+    kappa: f32,
+    max_steps: usize,
+    rng: &mut impl Rng,
+) -> Vec<f32> {
+    let mut rewards = Vec::new();
+    env.reset();
+    let mut s = env.state_id();
+
+    while !env.is_game_over() && rewards.len() < max_steps {
+        let aa = env.available_actions();
+        state.available.insert(s, aa.clone());
+
+        // Untried actions of a new state, for the Dyna-Q+ planning
+        if kappa > 0.0 {
+            for &a in aa.iter() {
+                if !state.model.contains_key(&(s, a)) {
+                    state.update_model(s, a, (s, 0.0, false));
+                }
             }
+        }
+
+        // Step (b): Choose action A using epsilon-greedy, with random ties so that
+        // the agent does not keep bumping into the same wall while Q is still 0
+        let a = if rng.gen::<f32>() < epsilon {
+            *aa.as_slice().choose(rng).unwrap()
+        } else {
+            greedy_action_random_ties(&aa, &state.q[s], rng)
+        };
+
+        let prev_score = env.score();
+        env.step(a);
+        let r = env.score() - prev_score;
+        let s_p = env.state_id();
+        let done = env.is_game_over();
+        rewards.push(r);
+        state.steps += 1;
+
+        // Step (d): Update Q(S, A)
+        if !done {
+            state.available.insert(s_p, env.available_actions());
+        }
+        let max_q_s_p = max_available_q(&state.q, &state.available, s_p, done);
+        state.q[s][a as usize] += alpha * (r + gamma * max_q_s_p - state.q[s][a as usize]);
 
-            // Move to the next state
-            s = s_p;
-            aa = aa_p;
+        // Step (e): Update the model
+        state.update_model(s, a, (s_p, r, done));
+        state.last_visit.insert((s, a), state.steps);
+
+        // Step (f): Perform planning
+        match state.distribution {
+            PlanningDistribution::Uniform => {
+                for _ in 0..planning_steps {
+                    // Randomly sample a previously observed state-action pair
+                    let &(s_rand, a_rand) = state.pairs.choose(rng).unwrap();
+                    state.plan(s_rand, a_rand, alpha, gamma, kappa);
+                }
+            }
+            PlanningDistribution::OnPolicy => {
+                // Follow the model from S', going back to S at the end of a simulated
+                // episode or in a state not left yet for real
+                let mut s_sim = if done { s } else { s_p };
+                for _ in 0..planning_steps {
+                    let mut modelled: Vec<i32> =
+                        (0..state.q[s_sim].len() as i32).filter(|&b| state.model.contains_key(&(s_sim, b))).collect();
+                    if modelled.is_empty() {
+                        s_sim = s;
+                        modelled = (0..state.q[s].len() as i32).filter(|&b| state.model.contains_key(&(s, b))).collect();
+                    }
+                    let a_sim = if rng.gen::<f32>() < epsilon {
+                        *modelled.choose(rng).unwrap()
+                    } else {
+                        greedy_action_random_ties(&DVector::from_vec(modelled), &state.q[s_sim], rng)
+                    };
+                    let (s_sim_p, done_sim) = state.plan(s_sim, a_sim, alpha, gamma, kappa);
+                    s_sim = if done_sim { s } else { s_sim_p };
+                }
+            }
         }
+
+        // Move to the next state
+        s = s_p;
     }
+    rewards
+}
 
-    (q.clone(), model.clone())
+/// Cumulative reward of Dyna-Q and Dyna-Q+ after each real step, averaged over
+/// `num_runs` runs of `num_steps` steps on fresh copies of `maze`
+#[allow(clippy::too_many_arguments)]
+pub fn compare_dyna_q_plus(
+    maze: &GridEnv,
+    num_steps: usize,
+    num_runs: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+    kappa: f32,
+) -> (Vec<f32>, Vec<f32>) {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut curves = [vec![0.0f32; num_steps], vec![0.0f32; num_steps]];
+
+    for _ in tqdm!(0..num_runs, position = 0) {
+        for (curve, bonus) in curves.iter_mut().zip([0.0, kappa]) {
+            let mut env = maze.clone();
+            let mut state = DynaQState::new(&env);
+            let mut total = 0.0;
+            while state.steps < num_steps {
+                let start = state.steps;
+                let rewards =
+                    dyna_q_episode(&mut env, &mut state, alpha, epsilon, gamma, planning_steps, bonus, num_steps - start, &mut rng);
+                for (i, r) in rewards.iter().enumerate() {
+                    total += r;
+                    curve[start + i] += total / num_runs as f32;
+                }
+            }
+        }
+    }
+    let [dyna_q, dyna_q_plus] = curves;
+    (dyna_q, dyna_q_plus)
 }
@@ -0,0 +1,172 @@
+use kdam::tqdm;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::back::envs::basic_env::Env;
+use crate::back::services::math::greedy_action_random_ties;
+
+/// How the simulations choose their actions once they leave the tree
+pub enum RolloutPolicy {
+    Uniform,
+    /// Greedy with respect to Q(s, a), indexed by the state ids of the env,
+    /// e.g. learned beforehand by Q-learning
+    Greedy(Vec<Vec<f32>>),
+}
+
+impl RolloutPolicy {
+    fn action(&self, env: &dyn Env, rng: &mut impl Rng) -> i32 {
+        let aa = env.available_actions();
+        match self {
+            RolloutPolicy::Uniform => *aa.as_slice().choose(rng).unwrap(),
+            RolloutPolicy::Greedy(q) => greedy_action_random_ties(&aa, &q[env.state_id()], rng),
+        }
+    }
+}
+
+/// Sequence of actions played from the root. The tree is open loop: a node
+/// averages the returns of every state its sequence led to.
+struct Node {
+    visits: u32,
+    /// Sum of the discounted returns from the parent, this node's action included
+    total_return: f32,
+    /// (action, index of the child)
+    children: Vec<(i32, usize)>,
+}
+
+impl Node {
+    fn new() -> Self {
+        Node { visits: 0, total_return: 0.0, children: Vec::new() }
+    }
+
+    fn value(&self) -> f32 {
+        self.total_return / self.visits as f32
+    }
+}
+
+/// UCT (Monte Carlo tree search with UCB1) planning from the current state of an
+/// env. Each simulation restores a snapshot of the real env into a separate
+/// instance, draws its hidden information again (see `Env::resample_hidden`),
+/// walks down the tree, then finishes the episode with the rollout policy. The
+/// env needs an exact snapshot, which the secret envs only have when they are
+/// deterministic (their snapshot replays the actions since the last reset).
+pub struct Mcts {
+    /// Number of simulations per decision
+    pub budget: usize,
+    /// Exploration constant c of UCB1
+    pub c: f32,
+    pub rollout: RolloutPolicy,
+    pub gamma: f32,
+    /// Maximum number of steps of a simulation, in the tree and in the rollout
+    pub max_depth: usize,
+}
+
+impl Mcts {
+    /// Action to play in the current state of `env`, which is left untouched, the
+    /// simulations running on `sim`, another instance of the same env
+    pub fn choose_action(&self, env: &dyn Env, sim: &mut dyn Env, rng: &mut impl Rng) -> i32 {
+        let root = env.snapshot();
+        let mut nodes = vec![Node::new()];
+
+        for _ in 0..self.budget {
+            sim.restore(&root);
+            sim.resample_hidden();
+
+            // Selection, down to a new node or the end of the episode
+            let mut path = vec![0];
+            let mut rewards = Vec::new();
+            while !sim.is_game_over() && rewards.len() < self.max_depth {
+                let node = path[path.len() - 1];
+                let aa = sim.available_actions();
+                let untried: Vec<i32> =
+                    aa.iter().copied().filter(|&a| !nodes[node].children.iter().any(|&(b, _)| b == a)).collect();
+
+                let (a, child, expanded) = match untried.choose(rng) {
+                    Some(&a) => {
+                        nodes.push(Node::new());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.push((a, child));
+                        (a, child, true)
+                    }
+                    None => {
+                        let (a, child) = self.select(&nodes, node, |a| aa.iter().any(|&b| b == a));
+                        (a, child, false)
+                    }
+                };
+
+                let prev_score = sim.score();
+                sim.step(a);
+                rewards.push(sim.score() - prev_score);
+                path.push(child);
+                if expanded {
+                    break;
+                }
+            }
+
+            // Rollout
+            let mut g = 0.0f32;
+            let mut discount = 1.0f32;
+            let mut depth = rewards.len();
+            while !sim.is_game_over() && depth < self.max_depth {
+                let a = self.rollout.action(sim, rng);
+                let prev_score = sim.score();
+                sim.step(a);
+                g += discount * (sim.score() - prev_score);
+                discount *= self.gamma;
+                depth += 1;
+            }
+
+            // Backup, each node getting the return from its parent
+            nodes[0].visits += 1;
+            for (i, &r) in rewards.iter().enumerate().rev() {
+                g = r + self.gamma * g;
+                let node = &mut nodes[path[i + 1]];
+                node.visits += 1;
+                node.total_return += g;
+            }
+        }
+
+        // Most visited action, the value breaking the ties
+        let aa = env.available_actions();
+        nodes[0]
+            .children
+            .iter()
+            .filter(|&&(a, _)| aa.iter().any(|&b| b == a))
+            .max_by(|&&(_, x), &&(_, y)| {
+                nodes[x].visits.cmp(&nodes[y].visits).then(nodes[x].value().total_cmp(&nodes[y].value()))
+            })
+            .map(|&(a, _)| a)
+            .unwrap_or_else(|| *aa.as_slice().choose(rng).expect("No action available"))
+    }
+
+    /// Child of `node` maximising UCB1 among the actions allowed by `available`
+    fn select(&self, nodes: &[Node], node: usize, available: impl Fn(i32) -> bool) -> (i32, usize) {
+        let ln_n = (nodes[node].visits.max(1) as f32).ln();
+        let ucb = |child: usize| nodes[child].value() + self.c * (ln_n / nodes[child].visits as f32).sqrt();
+        nodes[node]
+            .children
+            .iter()
+            .filter(|&&(a, _)| available(a))
+            .max_by(|&&(_, x), &&(_, y)| ucb(x).total_cmp(&ucb(y)))
+            .copied()
+            .unwrap()
+    }
+}
+
+/// Final score of each of `num_episodes` episodes played with MCTS choosing every
+/// action, its simulations running on `sim`
+pub fn evaluate_mcts(env: &mut dyn Env, sim: &mut dyn Env, mcts: &Mcts, num_episodes: usize) -> Vec<f32> {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut scores = Vec::new();
+    for _ in tqdm!(0..num_episodes, position = 0) {
+        env.reset();
+        while !env.is_game_over() {
+            let a = mcts.choose_action(env, sim, &mut rng);
+            env.step(a);
+        }
+        scores.push(env.score());
+    }
+    env.reset();
+    scores
+}
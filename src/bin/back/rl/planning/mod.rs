@@ -1 +1,3 @@
-pub mod dyna_q;
\ No newline at end of file
+pub mod dyna_q;
+pub mod prioritized_sweeping;
+pub mod count_model;pub mod mcts;
@@ -0,0 +1,249 @@
+use kdam::tqdm;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::back::envs::basic_env::Env;
+use crate::back::envs::grid_world_env::GridEnv;
+use crate::back::rl::planning::dyna_q::{dyna_q_episode, max_available_q, AvailableActions, DynaQState, Model};
+use crate::back::services::math::{greedy_action, greedy_action_random_ties};
+
+/// (S, A) waiting in the queue with its priority |TD error|
+struct Entry {
+    priority: f32,
+    s: usize,
+    a: i32,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.total_cmp(&other.priority)
+    }
+}
+
+/// Max priority queue of (S, A) pairs, holding each pair once with its highest priority
+struct PriorityQueue {
+    heap: BinaryHeap<Entry>,
+    /// Current priority of the pairs in the queue, the heap entries with another one are stale
+    priorities: HashMap<(usize, i32), f32>,
+}
+
+impl PriorityQueue {
+    fn new() -> Self {
+        PriorityQueue { heap: BinaryHeap::new(), priorities: HashMap::new() }
+    }
+
+    /// Inserts (s, a), or raises its priority if it is already queued with a lower one
+    fn push(&mut self, s: usize, a: i32, priority: f32) {
+        let current = self.priorities.entry((s, a)).or_insert(f32::MIN);
+        if priority > *current {
+            *current = priority;
+            self.heap.push(Entry { priority, s, a });
+        }
+    }
+
+    fn pop(&mut self) -> Option<(usize, i32)> {
+        while let Some(Entry { priority, s, a }) = self.heap.pop() {
+            if self.priorities.get(&(s, a)) == Some(&priority) {
+                self.priorities.remove(&(s, a));
+                return Some((s, a));
+            }
+        }
+        None
+    }
+}
+
+/// Everything prioritized sweeping learns during a run, kept between episodes
+pub struct SweepingState {
+    pub q: Vec<Vec<f32>>,
+    pub model: Model,
+    available: AvailableActions,
+    /// Pairs (S, A) observed to lead to each state
+    predecessors: HashMap<usize, HashSet<(usize, i32)>>,
+    queue: PriorityQueue,
+    /// Number of real steps played
+    pub steps: usize,
+}
+
+impl SweepingState {
+    pub fn new(env: &dyn Env) -> Self {
+        SweepingState {
+            q: vec![vec![0.0f32; env.num_actions()]; env.num_states()],
+            model: HashMap::new(),
+            available: HashMap::new(),
+            predecessors: HashMap::new(),
+            queue: PriorityQueue::new(),
+            steps: 0,
+        }
+    }
+
+    /// |TD error| of (s, a) under the model
+    fn priority(&self, s: usize, a: i32, gamma: f32) -> f32 {
+        let (s_p, r, done) = self.model[&(s, a)];
+        let max_q_s_p = max_available_q(&self.q, &self.available, s_p, done);
+        (r + gamma * max_q_s_p - self.q[s][a as usize]).abs()
+    }
+}
+
+/// Prioritized sweeping (Sutton & Barto, section 8.4): instead of planning on
+/// random pairs like Dyna-Q, the planning updates go to the pairs with the largest
+/// TD error, and each update queues the predecessors whose error exceeds `theta`
+pub fn prioritized_sweeping(
+    env: &mut dyn Env,
+    max_episodes: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+    theta: f32,
+) -> (Vec<Vec<f32>>, Model) {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut state = SweepingState::new(env);
+
+    for _ in tqdm!(0..max_episodes, position = 0) {
+        prioritized_sweeping_episode(env, &mut state, alpha, epsilon, gamma, planning_steps, theta, usize::MAX, &mut rng);
+    }
+
+    (state.q, state.model)
+}
+
+/// Plays one episode of at most `max_steps` real steps, with up to
+/// `planning_steps` prioritized updates after each of them, and returns the
+/// reward of each real step
+#[allow(clippy::too_many_arguments)]
+pub fn prioritized_sweeping_episode(
+    env: &mut dyn Env,
+    state: &mut SweepingState,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+    theta: f32,
+    max_steps: usize,
+    rng: &mut impl Rng,
+) -> Vec<f32> {
+    let mut rewards = Vec::new();
+    env.reset();
+    let mut s = env.state_id();
+
+    while !env.is_game_over() && rewards.len() < max_steps {
+        let aa = env.available_actions();
+        let a = if rng.gen::<f32>() < epsilon {
+            *aa.as_slice().choose(rng).unwrap()
+        } else {
+            greedy_action_random_ties(&aa, &state.q[s], rng)
+        };
+
+        let prev_score = env.score();
+        env.step(a);
+        let r = env.score() - prev_score;
+        let s_p = env.state_id();
+        let done = env.is_game_over();
+        rewards.push(r);
+        state.steps += 1;
+
+        // Update the model, then queue (S, A) if its TD error is large enough
+        if !done {
+            state.available.insert(s_p, env.available_actions());
+        }
+        state.model.insert((s, a), (s_p, r, done));
+        state.predecessors.entry(s_p).or_default().insert((s, a));
+        let priority = state.priority(s, a, gamma);
+        if priority > theta {
+            state.queue.push(s, a, priority);
+        }
+
+        // Planning, in priority order
+        for _ in 0..planning_steps {
+            let Some((s_plan, a_plan)) = state.queue.pop() else {
+                break;
+            };
+            let (s_plan_p, r_plan, done_plan) = state.model[&(s_plan, a_plan)];
+            let max_q_s_plan_p = max_available_q(&state.q, &state.available, s_plan_p, done_plan);
+            state.q[s_plan][a_plan as usize] += alpha * (r_plan + gamma * max_q_s_plan_p - state.q[s_plan][a_plan as usize]);
+
+            // The value of S changed: the pairs leading to it may need an update
+            if let Some(predecessors) = state.predecessors.get(&s_plan) {
+                for &(s_pred, a_pred) in predecessors {
+                    let priority = state.priority(s_pred, a_pred, gamma);
+                    if priority > theta {
+                        state.queue.push(s_pred, a_pred, priority);
+                    }
+                }
+            }
+        }
+
+        s = s_p;
+    }
+    rewards
+}
+
+/// Whether the greedy policy of `q` goes from the start to the goal along a
+/// shortest path of `optimal` steps
+fn is_optimal(env: &mut dyn Env, q: &[Vec<f32>], optimal: usize) -> bool {
+    env.reset();
+    for _ in 0..optimal {
+        if env.is_game_over() {
+            return false;
+        }
+        env.step(greedy_action(&env.available_actions(), &q[env.state_id()]));
+    }
+    env.is_game_over()
+}
+
+/// Number of real steps Dyna-Q and prioritized sweeping need before their greedy
+/// policy is optimal on `maze`, for each of the `num_runs` runs (`None` when
+/// not reached within `max_steps`). The policy is checked after each episode.
+#[allow(clippy::too_many_arguments)]
+pub fn compare_steps_to_optimal(
+    maze: &GridEnv,
+    num_runs: usize,
+    max_steps: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    planning_steps: usize,
+    theta: f32,
+) -> (Vec<Option<usize>>, Vec<Option<usize>>) {
+    let optimal = maze.shortest_path_length().expect("The goal cannot be reached");
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut dyna_q_steps = Vec::new();
+    let mut sweeping_steps = Vec::new();
+
+    for _ in tqdm!(0..num_runs, position = 0) {
+        let mut env = maze.clone();
+        let mut state = DynaQState::new(&env);
+        while state.steps < max_steps && !is_optimal(&mut env, &state.q, optimal) {
+            let remaining = max_steps - state.steps;
+            dyna_q_episode(&mut env, &mut state, alpha, epsilon, gamma, planning_steps, 0.0, remaining, &mut rng);
+        }
+        dyna_q_steps.push(if is_optimal(&mut env, &state.q, optimal) { Some(state.steps) } else { None });
+
+        let mut env = maze.clone();
+        let mut state = SweepingState::new(&env);
+        while state.steps < max_steps && !is_optimal(&mut env, &state.q, optimal) {
+            let remaining = max_steps - state.steps;
+            prioritized_sweeping_episode(&mut env, &mut state, alpha, epsilon, gamma, planning_steps, theta, remaining, &mut rng);
+        }
+        sweeping_steps.push(if is_optimal(&mut env, &state.q, optimal) { Some(state.steps) } else { None });
+    }
+    (dyna_q_steps, sweeping_steps)
+}
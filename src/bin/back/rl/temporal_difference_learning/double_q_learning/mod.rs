@@ -0,0 +1,93 @@
+use kdam::tqdm;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::back::envs::basic_env::Env;
+use crate::back::envs::maximization_bias_env::{MaximizationBiasEnv, LEFT};
+use crate::back::rl::temporal_difference_learning::q_learning::q_learning_episode;
+use crate::back::services::math::{epsilon_greedy_action, greedy_action};
+
+/// Double Q-learning: one table picks the best next action, the other one
+/// evaluates it, which removes the maximisation bias of Q-learning.
+/// Returns both tables, act greedily on their sum.
+pub fn double_q_learning(env: &mut dyn Env, max_episodes: usize, alpha: f32, epsilon: f32, gamma: f32) -> (Vec<Vec<f32>>, Vec<Vec<f32>>) {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut q1 = vec![vec![0.0f32; env.num_actions()]; env.num_states()];
+    let mut q2 = q1.clone();
+
+    for _ in tqdm!(0..max_episodes, position = 0) {
+        double_q_learning_episode(env, &mut q1, &mut q2, alpha, epsilon, gamma, &mut rng);
+    }
+    (q1, q2)
+}
+
+/// Plays one episode, ε-greedy on Q1 + Q2, and returns the actions taken
+pub fn double_q_learning_episode(
+    env: &mut dyn Env,
+    q1: &mut [Vec<f32>],
+    q2: &mut [Vec<f32>],
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    rng: &mut impl Rng,
+) -> Vec<i32> {
+    let mut actions = Vec::new();
+    env.reset();
+    let mut s = env.state_id();
+
+    while !env.is_game_over() {
+        // Same action selection as Q-learning, on a one-state table holding Q1(s, .) + Q2(s, .)
+        let a = epsilon_greedy_action(env.available_actions(), &[sum_row(q1, q2, s)], 0, epsilon, rng);
+        actions.push(a);
+
+        let prev_score = env.score();
+        env.step(a);
+        let r = env.score() - prev_score;
+
+        let s_p = env.state_id();
+        let aa_p = env.available_actions();
+
+        // Update one table at random, with its own greedy action evaluated by the other
+        let (q_update, q_eval) = if rng.gen::<bool>() { (&mut *q1, &*q2) } else { (&mut *q2, &*q1) };
+        let q_s_p = if env.is_game_over() {
+            0.0f32
+        } else {
+            q_eval[s_p][greedy_action(&aa_p, &q_update[s_p]) as usize]
+        };
+        let a = a as usize;
+        q_update[s][a] += alpha * (r + gamma * q_s_p - q_update[s][a]);
+
+        s = s_p;
+    }
+    actions
+}
+
+/// Q1(s, .) + Q2(s, .)
+pub fn sum_row(q1: &[Vec<f32>], q2: &[Vec<f32>], s: usize) -> Vec<f32> {
+    q1[s].iter().zip(q2[s].iter()).map(|(a, b)| a + b).collect()
+}
+
+/// Fraction of the runs going left from A at each episode, for Q-learning and
+/// Double Q-learning on the maximisation bias MDP
+pub fn compare_maximization_bias(num_runs: usize, num_episodes: usize, alpha: f32, epsilon: f32, gamma: f32) -> (Vec<f32>, Vec<f32>) {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut env = MaximizationBiasEnv::new();
+    let mut left_q = vec![0.0f32; num_episodes];
+    let mut left_double_q = vec![0.0f32; num_episodes];
+
+    for _ in tqdm!(0..num_runs, position = 0) {
+        let mut q = vec![vec![0.0f32; env.num_actions()]; env.num_states()];
+        let mut q1 = q.clone();
+        let mut q2 = q.clone();
+
+        for episode in 0..num_episodes {
+            if q_learning_episode(&mut env, &mut q, alpha, epsilon, gamma, &mut rng)[0] == LEFT {
+                left_q[episode] += 1.0 / num_runs as f32;
+            }
+            if double_q_learning_episode(&mut env, &mut q1, &mut q2, alpha, epsilon, gamma, &mut rng)[0] == LEFT {
+                left_double_q[episode] += 1.0 / num_runs as f32;
+            }
+        }
+    }
+    (left_q, left_double_q)
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use kdam::tqdm;
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::back::envs::basic_env::Env;
+use crate::back::services::math::{epsilon_greedy_action, greedy_action, sample_action};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceKind {
+    /// e += 1
+    Accumulating,
+    /// e = 1
+    Replacing,
+    /// e = (1 - α) e + 1, which turns TD(λ) and SARSA(λ) into their true online
+    /// versions (van Seijen et al., 2016)
+    Dutch,
+}
+
+/// Eligibility traces, only storing the non-zero ones so that a step costs
+/// the number of recently visited entries instead of the size of the table
+struct Traces {
+    kind: TraceKind,
+    values: HashMap<usize, f32>,
+}
+
+impl Traces {
+    fn new(kind: TraceKind) -> Self {
+        Traces { kind, values: HashMap::new() }
+    }
+
+    fn visit(&mut self, index: usize, alpha: f32) {
+        let e = self.values.entry(index).or_insert(0.0);
+        *e = match self.kind {
+            TraceKind::Accumulating => *e + 1.0,
+            TraceKind::Replacing => 1.0,
+            TraceKind::Dutch => (1.0 - alpha) * *e + 1.0,
+        };
+    }
+
+    /// Decays every trace by `factor`, forgetting the negligible ones
+    fn decay(&mut self, factor: f32) {
+        self.values.retain(|_, e| {
+            *e *= factor;
+            *e > 1e-4
+        });
+    }
+
+    fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+/// TD(λ) prediction of V^pi with backward-view traces. With dutch traces this is
+/// true online TD(λ), whose updates add α (V(s) - V_old) e - α (V(s) - V_old) 1_s,
+/// V_old being the estimate of V(s) made at the previous step.
+pub fn td_lambda(
+    env: &mut dyn Env,
+    pi: &[Vec<f32>],
+    lambda: f32,
+    alpha: f32,
+    gamma: f32,
+    kind: TraceKind,
+    num_episodes: usize,
+) -> Vec<f32> {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut v = vec![0.0f32; env.num_states()];
+    let mut traces = Traces::new(kind);
+
+    for _ in tqdm!(0..num_episodes, position = 0) {
+        env.reset();
+        traces.clear();
+        let mut s = env.state_id();
+        let mut v_old = 0.0;
+
+        while !env.is_game_over() {
+            let a = sample_action(&pi[s], &env.available_actions(), &mut rng);
+            let prev_score = env.score();
+            env.step(a);
+            let r = env.score() - prev_score;
+            let s_p = env.state_id();
+
+            let v_s = v[s];
+            let v_s_p = if env.is_game_over() { 0.0 } else { v[s_p] };
+            let delta = r + gamma * v_s_p - v_s;
+            let correction = if kind == TraceKind::Dutch { v_s - v_old } else { 0.0 };
+            traces.visit(s, alpha);
+            for (&state, &e) in traces.values.iter() {
+                v[state] += alpha * (delta + correction) * e;
+            }
+            v[s] -= alpha * correction;
+            traces.decay(gamma * lambda);
+
+            v_old = v_s_p;
+            s = s_p;
+        }
+    }
+    v
+}
+
+/// SARSA(λ), on-policy with ε-greedy actions, true online SARSA(λ) with dutch traces
+pub fn sarsa_lambda(
+    env: &mut dyn Env,
+    lambda: f32,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    kind: TraceKind,
+    num_episodes: usize,
+) -> Vec<Vec<f32>> {
+    lambda_control(env, lambda, alpha, epsilon, gamma, kind, num_episodes, false)
+}
+
+/// Watkins's Q(λ): off-policy, the traces are cut after each exploratory action.
+/// Dutch traces are not supported, they need the true online updates.
+pub fn watkins_q_lambda(
+    env: &mut dyn Env,
+    lambda: f32,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    kind: TraceKind,
+    num_episodes: usize,
+) -> Vec<Vec<f32>> {
+    if kind == TraceKind::Dutch {
+        panic!("Watkins's Q(λ) does not support dutch traces");
+    }
+    lambda_control(env, lambda, alpha, epsilon, gamma, kind, num_episodes, true)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn lambda_control(
+    env: &mut dyn Env,
+    lambda: f32,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    kind: TraceKind,
+    num_episodes: usize,
+    watkins: bool,
+) -> Vec<Vec<f32>> {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let num_actions = env.num_actions();
+    let mut q = vec![vec![0.0f32; num_actions]; env.num_states()];
+    let mut traces = Traces::new(kind);
+
+    for _ in tqdm!(0..num_episodes, position = 0) {
+        env.reset();
+        traces.clear();
+        let mut s = env.state_id();
+        let mut a = epsilon_greedy_action(env.available_actions(), &q, s, epsilon, &mut rng) as usize;
+        let mut q_old = 0.0;
+
+        while !env.is_game_over() {
+            let prev_score = env.score();
+            env.step(a as i32);
+            let r = env.score() - prev_score;
+            let s_p = env.state_id();
+
+            // Next action, and whether it is greedy (a tie with the best action counts as greedy)
+            let (a_p, q_target, greedy) = if env.is_game_over() {
+                (0, 0.0, true)
+            } else {
+                let aa_p = env.available_actions();
+                let a_p = epsilon_greedy_action(aa_p.clone(), &q, s_p, epsilon, &mut rng) as usize;
+                let best = q[s_p][greedy_action(&aa_p, &q[s_p]) as usize];
+                let q_target = if watkins { best } else { q[s_p][a_p] };
+                (a_p, q_target, q[s_p][a_p] >= best)
+            };
+
+            let q_s_a = q[s][a];
+            let delta = r + gamma * q_target - q_s_a;
+            // True online SARSA(λ) with dutch traces
+            let correction = if kind == TraceKind::Dutch { q_s_a - q_old } else { 0.0 };
+            traces.visit(s * num_actions + a, alpha);
+            for (&index, &e) in traces.values.iter() {
+                q[index / num_actions][index % num_actions] += alpha * (delta + correction) * e;
+            }
+            q[s][a] -= alpha * correction;
+
+            if watkins && !greedy {
+                traces.clear();
+            } else {
+                traces.decay(gamma * lambda);
+            }
+
+            q_old = q_target;
+            s = s_p;
+            a = a_p;
+        }
+    }
+    q
+}
@@ -0,0 +1,43 @@
+use kdam::tqdm;
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::back::envs::basic_env::Env;
+use crate::back::services::math::{epsilon_greedy_action, epsilon_greedy_expectation};
+
+/// Expected SARSA: bootstraps on the expectation of Q(s', .) under the ε-greedy
+/// policy over the actions available in s'. Q is indexed by action id, so it
+/// works the same whether the action space changes between states or not.
+pub fn expected_sarsa(env: &mut dyn Env, max_episodes: usize, alpha: f32, epsilon: f32, gamma: f32) -> Vec<Vec<f32>> {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut q = vec![vec![0.0f32; env.num_actions()]; env.num_states()];
+
+    for _ in tqdm!(0..max_episodes, position = 0) {
+        env.reset();
+        let mut s = env.state_id();
+        let mut aa = env.available_actions();
+
+        while !env.is_game_over() {
+            let a = epsilon_greedy_action(aa, &q, s, epsilon, &mut rng) as usize;
+
+            let prev_score = env.score();
+            env.step(a as i32);
+            let r = env.score() - prev_score;
+
+            let s_p = env.state_id();
+            let aa_p = env.available_actions();
+
+            let expected_q_s_p = if env.is_game_over() {
+                0.0f32
+            } else {
+                epsilon_greedy_expectation(&aa_p, &q[s_p], epsilon)
+            };
+
+            q[s][a] += alpha * (r + gamma * expected_q_s_p - q[s][a]);
+
+            s = s_p;
+            aa = aa_p;
+        }
+    }
+    q
+}
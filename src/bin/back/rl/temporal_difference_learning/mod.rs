@@ -1,2 +1,7 @@
 pub mod q_learning;
-pub mod sarsa;
\ No newline at end of file
+pub mod double_q_learning;
+pub mod sarsa;
+pub mod expected_sarsa;
+pub mod n_step;
+pub mod eligibility_traces;
+pub mod q_sigma;
\ No newline at end of file
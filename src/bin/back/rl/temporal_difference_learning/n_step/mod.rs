@@ -0,0 +1,212 @@
+use kdam::tqdm;
+use nalgebra::DVector;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::back::envs::basic_env::Env;
+use crate::back::envs::line_world_env::LineEnv;
+use crate::back::rl::dynamic_programming::iterative_policy_evaluation::iterative_policy_evaluation;
+use crate::back::services::math::{epsilon_greedy_action, epsilon_greedy_expectation, sample_action};
+
+/// Fixed size buffer indexed by time step, keeping the last `capacity` values
+pub struct CircularBuffer<T> {
+    values: Vec<T>,
+}
+
+impl<T: Clone> CircularBuffer<T> {
+    pub fn new(capacity: usize, default: T) -> Self {
+        CircularBuffer { values: vec![default; capacity] }
+    }
+
+    pub fn get(&self, t: usize) -> &T {
+        &self.values[t % self.values.len()]
+    }
+
+    pub fn set(&mut self, t: usize, value: T) {
+        let len = self.values.len();
+        self.values[t % len] = value;
+    }
+}
+
+/// n-step return G_{tau:tau+n} without the bootstrap term, from R_{tau+1} to R_{min(tau+n, T)}
+fn n_step_rewards(rewards: &CircularBuffer<f32>, tau: usize, n: usize, end: usize, gamma: f32) -> f32 {
+    (tau + 1..=(tau + n).min(end))
+        .map(|i| gamma.powi((i - tau - 1) as i32) * rewards.get(i))
+        .sum()
+}
+
+/// n-step TD prediction of V^pi (n = 1 is TD(0), a large n is Monte Carlo)
+pub fn n_step_td_prediction(
+    env: &mut dyn Env,
+    pi: &[Vec<f32>],
+    n: usize,
+    alpha: f32,
+    gamma: f32,
+    num_episodes: usize,
+) -> Vec<f32> {
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut v = vec![0.0f32; env.num_states()];
+    for _ in tqdm!(0..num_episodes, position = 0) {
+        n_step_td_episode(env, pi, &mut v, n, alpha, gamma, &mut rng);
+    }
+    v
+}
+
+/// One episode of n-step TD prediction, updating `v`
+pub fn n_step_td_episode(
+    env: &mut dyn Env,
+    pi: &[Vec<f32>],
+    v: &mut [f32],
+    n: usize,
+    alpha: f32,
+    gamma: f32,
+    rng: &mut impl Rng,
+) {
+    let n = n.max(1);
+    let mut states = CircularBuffer::new(n + 1, 0usize);
+    let mut rewards = CircularBuffer::new(n + 1, 0.0f32);
+
+    env.reset();
+    states.set(0, env.state_id());
+    let mut end = usize::MAX;
+    let mut t = 0;
+
+    loop {
+        if t < end {
+            let a = sample_action(&pi[*states.get(t)], &env.available_actions(), rng);
+            let prev_score = env.score();
+            env.step(a);
+            rewards.set(t + 1, env.score() - prev_score);
+            states.set(t + 1, env.state_id());
+            if env.is_game_over() {
+                end = t + 1;
+            }
+        }
+
+        // tau is the time whose estimate is being updated
+        if t + 1 >= n {
+            let tau = t + 1 - n;
+            let mut g = n_step_rewards(&rewards, tau, n, end, gamma);
+            if tau + n < end {
+                g += gamma.powi(n as i32) * v[*states.get(tau + n)];
+            }
+            let s_tau = *states.get(tau);
+            v[s_tau] += alpha * (g - v[s_tau]);
+
+            if tau + 1 == end {
+                break;
+            }
+        }
+        t += 1;
+    }
+}
+
+/// n-step SARSA, bootstrapping on Q(S_{t+n}, A_{t+n})
+pub fn n_step_sarsa(env: &mut dyn Env, n: usize, alpha: f32, epsilon: f32, gamma: f32, num_episodes: usize) -> Vec<Vec<f32>> {
+    n_step_control(env, n, alpha, epsilon, gamma, num_episodes, false).0
+}
+
+/// n-step Expected SARSA, bootstrapping on the ε-greedy expectation of Q(S_{t+n}, .)
+pub fn n_step_expected_sarsa(env: &mut dyn Env, n: usize, alpha: f32, epsilon: f32, gamma: f32, num_episodes: usize) -> Vec<Vec<f32>> {
+    n_step_control(env, n, alpha, epsilon, gamma, num_episodes, true).0
+}
+
+/// n-step SARSA (or Expected SARSA), also returning the discounted return of each episode
+pub fn n_step_control(
+    env: &mut dyn Env,
+    n: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    num_episodes: usize,
+    expected: bool,
+) -> (Vec<Vec<f32>>, Vec<f32>) {
+    let n = n.max(1);
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut q = vec![vec![0.0f32; env.num_actions()]; env.num_states()];
+
+    // (S_t, available actions in S_t, A_t) and R_t
+    let mut steps = CircularBuffer::new(n + 1, (0usize, DVector::<i32>::zeros(0), 0usize));
+    let mut rewards = CircularBuffer::new(n + 1, 0.0f32);
+    let mut returns = Vec::with_capacity(num_episodes);
+
+    for _ in tqdm!(0..num_episodes, position = 0) {
+        let mut episode_return = 0.0;
+        env.reset();
+        let aa = env.available_actions();
+        let a = epsilon_greedy_action(aa.clone(), &q, env.state_id(), epsilon, &mut rng) as usize;
+        steps.set(0, (env.state_id(), aa, a));
+        let mut end = usize::MAX;
+        let mut t = 0;
+
+        loop {
+            if t < end {
+                let a = steps.get(t).2;
+                let prev_score = env.score();
+                env.step(a as i32);
+                let r = env.score() - prev_score;
+                rewards.set(t + 1, r);
+                episode_return += gamma.powi(t as i32) * r;
+
+                let s_p = env.state_id();
+                if env.is_game_over() {
+                    end = t + 1;
+                    steps.set(t + 1, (s_p, DVector::zeros(0), 0));
+                } else {
+                    let aa_p = env.available_actions();
+                    let a_p = epsilon_greedy_action(aa_p.clone(), &q, s_p, epsilon, &mut rng) as usize;
+                    steps.set(t + 1, (s_p, aa_p, a_p));
+                }
+            }
+
+            if t + 1 >= n {
+                let tau = t + 1 - n;
+                let mut g = n_step_rewards(&rewards, tau, n, end, gamma);
+                if tau + n < end {
+                    let (s_n, aa_n, a_n) = steps.get(tau + n);
+                    g += gamma.powi(n as i32)
+                        * if expected { epsilon_greedy_expectation(aa_n, &q[*s_n], epsilon) } else { q[*s_n][*a_n] };
+                }
+                let (s_tau, a_tau) = (steps.get(tau).0, steps.get(tau).2);
+                q[s_tau][a_tau] += alpha * (g - q[s_tau][a_tau]);
+
+                if tau + 1 == end {
+                    break;
+                }
+            }
+            t += 1;
+        }
+        returns.push(episode_return);
+    }
+    (q, returns)
+}
+
+/// Random walk of Sutton & Barto (example 7.1): RMS error of n-step TD on a
+/// line of `num_states` non-terminal states under the random policy, averaged
+/// over the first `num_episodes` episodes and `num_runs` runs.
+/// Returns `errors[i][j]` for `ns[i]` and `alphas[j]`.
+pub fn random_walk_sweep(num_states: usize, ns: &[usize], alphas: &[f32], num_episodes: usize, num_runs: usize) -> Vec<Vec<f32>> {
+    let mut env = LineEnv::with_size(num_states + 2);
+    let pi = vec![vec![0.5, 0.5]; num_states + 2];
+    let s = (0..env.num_states()).collect();
+    let a = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let truth = iterative_policy_evaluation(&pi, &s, &a, &r, &mut env, 1.0, 1e-6);
+
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut errors = vec![vec![0.0f32; alphas.len()]; ns.len()];
+    for _ in tqdm!(0..num_runs, position = 0) {
+        for (i, &n) in ns.iter().enumerate() {
+            for (j, &alpha) in alphas.iter().enumerate() {
+                let mut v = vec![0.0f32; env.num_states()];
+                for _ in 0..num_episodes {
+                    n_step_td_episode(&mut env, &pi, &mut v, n, alpha, 1.0, &mut rng);
+                    let squared: f32 = (1..=num_states).map(|s| (v[s] - truth[s]).powi(2)).sum();
+                    errors[i][j] += (squared / num_states as f32).sqrt();
+                }
+            }
+        }
+    }
+    let total = (num_runs * num_episodes) as f32;
+    errors.iter().map(|row| row.iter().map(|e| e / total).collect()).collect()
+}
@@ -1,11 +1,11 @@
 use kdam::tqdm;
 
 use rand::prelude::SliceRandom;
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 use rand_xoshiro::Xoshiro256PlusPlus;
 
 use crate::back::envs::basic_env::Env;
-use crate::back::services::math::{max, epsilon_greedy_action};
+use crate::back::services::math::{max, epsilon_greedy_action, greedy_action};
 
 pub fn q_learning(env: &mut dyn Env, max_episodes: usize, alpha: f32, epsilon: f32, gamma: f32) -> Vec<Vec<f32>> {
     let mut rng = Xoshiro256PlusPlus::from_entropy();
@@ -20,34 +20,43 @@ pub fn q_learning(env: &mut dyn Env, max_episodes: usize, alpha: f32, epsilon: f
     }
 
     for _ in tqdm!(0..max_episodes, position = 0) {
-        env.reset();
-        let mut s = env.state_id();
-        let mut aa = env.available_actions();
+        q_learning_episode(env, &mut q, alpha, epsilon, gamma, &mut rng);
+    }
 
-        while !env.is_game_over() {
-            let a = epsilon_greedy_action(aa, &q, s, epsilon, &mut rng);
+    q.clone()
+}
 
-            let prev_score = env.score();
-            env.step(a);
-            let r = env.score() - prev_score;
+/// Plays one episode, updating `q` along the way, and returns the actions taken
+pub fn q_learning_episode(env: &mut dyn Env, q: &mut [Vec<f32>], alpha: f32, epsilon: f32, gamma: f32, rng: &mut impl Rng) -> Vec<i32> {
+    let mut actions = Vec::new();
+    env.reset();
+    let mut s = env.state_id();
+    let mut aa = env.available_actions();
 
-            let s_p = env.state_id();
-            let aa_p = env.available_actions();
+    while !env.is_game_over() {
+        let a = epsilon_greedy_action(aa, q, s, epsilon, rng);
+        actions.push(a);
 
-            let max_q_s_p = if env.is_game_over() {
-                0.0f32
-            } else {
-                max(&q[s_p])
-            };
+        let prev_score = env.score();
+        env.step(a);
+        let r = env.score() - prev_score;
 
-            q[s][a as usize] = q[s][a as usize] + alpha * (r + gamma * max_q_s_p - q[s][a as usize]);
+        let s_p = env.state_id();
+        let aa_p = env.available_actions();
 
-            s = s_p;
-            aa = aa_p;
-        }
-    }
+        // Only the actions available in s' can be taken from there
+        let max_q_s_p = if env.is_game_over() {
+            0.0f32
+        } else {
+            q[s_p][greedy_action(&aa_p, &q[s_p]) as usize]
+        };
 
-    q.clone()
+        q[s][a as usize] = q[s][a as usize] + alpha * (r + gamma * max_q_s_p - q[s][a as usize]);
+
+        s = s_p;
+        aa = aa_p;
+    }
+    actions
 }
 
 pub fn q_learning_dynamic(env: &mut dyn Env, max_episodes: usize, alpha: f32, epsilon: f32, gamma: f32, action_spaces: &[usize]) -> Vec<Vec<f32>> {
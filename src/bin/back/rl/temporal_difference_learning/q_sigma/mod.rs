@@ -0,0 +1,172 @@
+use kdam::tqdm;
+use nalgebra::DVector;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::back::envs::basic_env::Env;
+use crate::back::rl::temporal_difference_learning::n_step::{n_step_control, CircularBuffer};
+use crate::back::services::math::{epsilon_greedy_expectation, epsilon_greedy_probabilities, sample_action};
+
+/// Degree of sampling σ of each step: 1 samples the next action like SARSA,
+/// 0 takes the expectation over all actions like Tree Backup
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SigmaSchedule {
+    Fixed(f32),
+    /// 1, 0, 1, 0, ... along the episode
+    Alternating,
+    /// σ = 1 with the given probability, 0 otherwise, drawn at each step
+    Random(f32),
+    /// σ = factor^episode: starts like SARSA and moves towards Tree Backup
+    Decaying(f32),
+}
+
+impl SigmaSchedule {
+    fn sigma(&self, t: usize, episode: usize, rng: &mut impl Rng) -> f32 {
+        match *self {
+            SigmaSchedule::Fixed(sigma) => sigma,
+            SigmaSchedule::Alternating => ((t + 1) % 2) as f32,
+            SigmaSchedule::Random(p) => {
+                if rng.gen::<f32>() < p {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            SigmaSchedule::Decaying(factor) => factor.powi(episode as i32),
+        }
+    }
+}
+
+/// n-step Tree Backup: off-policy learning of the greedy policy from ε-greedy
+/// episodes, without importance sampling.
+/// Returns Q and the discounted return of each episode.
+pub fn tree_backup(env: &mut dyn Env, n: usize, alpha: f32, epsilon: f32, gamma: f32, num_episodes: usize) -> (Vec<Vec<f32>>, Vec<f32>) {
+    q_sigma(env, n, SigmaSchedule::Fixed(0.0), alpha, epsilon, 0.0, gamma, num_episodes)
+}
+
+/// Off-policy n-step Q(σ) (Sutton & Barto, section 7.6). The behaviour policy
+/// is ε-greedy on Q, the target policy is `target_epsilon`-greedy: with the same
+/// epsilon the importance ratios are all 1 and σ = 1 is n-step SARSA.
+/// Returns Q and the discounted return of each episode.
+#[allow(clippy::too_many_arguments)]
+pub fn q_sigma(
+    env: &mut dyn Env,
+    n: usize,
+    schedule: SigmaSchedule,
+    alpha: f32,
+    epsilon: f32,
+    target_epsilon: f32,
+    gamma: f32,
+    num_episodes: usize,
+) -> (Vec<Vec<f32>>, Vec<f32>) {
+    let n = n.max(1);
+    let mut rng = Xoshiro256PlusPlus::from_entropy();
+    let mut q = vec![vec![0.0f32; env.num_actions()]; env.num_states()];
+
+    // (S_t, available actions in S_t, A_t, b(A_t|S_t), σ_t) and R_t
+    let mut steps = CircularBuffer::new(n + 1, (0usize, DVector::<i32>::zeros(0), 0usize, 1.0f32, 0.0f32));
+    let mut rewards = CircularBuffer::new(n + 1, 0.0f32);
+    let mut returns = Vec::with_capacity(num_episodes);
+
+    for episode in tqdm!(0..num_episodes, position = 0) {
+        let mut episode_return = 0.0;
+        env.reset();
+        let (s, aa) = (env.state_id(), env.available_actions());
+        let b_s = epsilon_greedy_probabilities(&aa, &q[s], epsilon);
+        let a = sample_action(&b_s, &aa, &mut rng) as usize;
+        steps.set(0, (s, aa, a, b_s[a], schedule.sigma(0, episode, &mut rng)));
+        let mut end = usize::MAX;
+        let mut t = 0;
+
+        loop {
+            if t < end {
+                let prev_score = env.score();
+                env.step(steps.get(t).2 as i32);
+                let r = env.score() - prev_score;
+                rewards.set(t + 1, r);
+                episode_return += gamma.powi(t as i32) * r;
+
+                let s_p = env.state_id();
+                if env.is_game_over() {
+                    end = t + 1;
+                    steps.set(t + 1, (s_p, DVector::zeros(0), 0, 1.0, 0.0));
+                } else {
+                    let aa_p = env.available_actions();
+                    let b_s_p = epsilon_greedy_probabilities(&aa_p, &q[s_p], epsilon);
+                    let a_p = sample_action(&b_s_p, &aa_p, &mut rng) as usize;
+                    steps.set(t + 1, (s_p, aa_p, a_p, b_s_p[a_p], schedule.sigma(t + 1, episode, &mut rng)));
+                }
+            }
+
+            if t + 1 >= n {
+                let tau = t + 1 - n;
+
+                // G_{k-1:h} = R_k + γ (σ_k ρ_k + (1 - σ_k) π(A_k|S_k)) (G_{k:h} - Q(S_k, A_k)) + γ V̄(S_k)
+                let h = (t + 1).min(end);
+                let mut g = if h == end {
+                    rewards.get(end).to_owned()
+                } else {
+                    let (s_h, _, a_h, _, _) = steps.get(h);
+                    q[*s_h][*a_h]
+                };
+                let from = if h == end { end - 1 } else { h };
+                for k in (tau + 1..=from).rev() {
+                    let (s_k, aa_k, a_k, b_k, sigma_k) = steps.get(k);
+                    let pi_k = epsilon_greedy_probabilities(aa_k, &q[*s_k], target_epsilon);
+                    let v_bar = epsilon_greedy_expectation(aa_k, &q[*s_k], target_epsilon);
+                    let rho_k = pi_k[*a_k] / b_k;
+                    let weight = sigma_k * rho_k + (1.0 - sigma_k) * pi_k[*a_k];
+                    g = rewards.get(k) + gamma * weight * (g - q[*s_k][*a_k]) + gamma * v_bar;
+                }
+
+                let (s_tau, a_tau) = (steps.get(tau).0, steps.get(tau).2);
+                q[s_tau][a_tau] += alpha * (g - q[s_tau][a_tau]);
+
+                if tau + 1 == end {
+                    break;
+                }
+            }
+            t += 1;
+        }
+        returns.push(episode_return);
+    }
+    (q, returns)
+}
+
+/// Average learning curves (discounted return per episode) of n-step SARSA,
+/// Tree Backup and Q(σ) with several schedules, over `num_runs` runs on `env`
+pub fn compare_n_step_methods(
+    env: &mut dyn Env,
+    n: usize,
+    alpha: f32,
+    epsilon: f32,
+    gamma: f32,
+    num_episodes: usize,
+    num_runs: usize,
+) -> Vec<(String, Vec<f32>)> {
+    let schedules = [
+        SigmaSchedule::Fixed(0.5),
+        SigmaSchedule::Alternating,
+        SigmaSchedule::Random(0.5),
+        SigmaSchedule::Decaying(0.95),
+    ];
+    let mut names = vec!["n-step SARSA".to_string(), "Tree Backup".to_string()];
+    names.extend(schedules.iter().map(|schedule| format!("Q(σ) {:?}", schedule)));
+    let mut curves = vec![vec![0.0f32; num_episodes]; names.len()];
+
+    for _ in 0..num_runs {
+        let mut runs = vec![
+            n_step_control(env, n, alpha, epsilon, gamma, num_episodes, false).1,
+            tree_backup(env, n, alpha, epsilon, gamma, num_episodes).1,
+        ];
+        for &schedule in &schedules {
+            runs.push(q_sigma(env, n, schedule, alpha, epsilon, epsilon, gamma, num_episodes).1);
+        }
+        for (curve, returns) in curves.iter_mut().zip(runs.iter()) {
+            for (total, r) in curve.iter_mut().zip(returns.iter()) {
+                *total += r / num_runs as f32;
+            }
+        }
+    }
+    names.into_iter().zip(curves).collect()
+}
@@ -1,17 +1,38 @@
 use std::io;
-use std::{thread, time::Duration};
+use std::{thread, time::{Duration, Instant}};
 use rand::Rng;
 use crate::back::envs::basic_env::Env;
+use crate::back::envs::line_world_env::LineEnv;
+use crate::back::rl::dynamic_programming::exact_policy_evaluation::{exact_policy_evaluation, exact_policy_evaluation_deterministic};
+use crate::back::rl::dynamic_programming::finite_horizon::backward_induction;
 use crate::back::rl::dynamic_programming::iterative_policy_evaluation::iterative_policy_evaluation;
-use crate::back::rl::dynamic_programming::policy_iteration::policy_iteration;
-use crate::back::rl::dynamic_programming::value_iteration::value_iteration;
+use crate::back::rl::dynamic_programming::linear_programming::{constrained_mdp, linear_programming, linear_programming_dual};
+use crate::back::rl::dynamic_programming::policy_iteration::{modified_policy_iteration, policy_iteration};
+use crate::back::rl::dynamic_programming::q_value_iteration::{q_policy_iteration, q_value_iteration};
+use crate::back::rl::dynamic_programming::rtdp::{compare_backups_to_convergence, rtdp};
+use crate::back::rl::dynamic_programming::value_iteration::{value_iteration, value_iteration_with, BackupScheme, ValueIterationReport, BACKUP_SCHEMES};
 use crate::back::rl::monte_carlo::es::monte_carlo_es;
-use crate::back::rl::monte_carlo::off_policy::{off_policy_mc_control_dynamic, off_policy_mc_control_secret};
+use crate::back::rl::monte_carlo::importance_sampling::compare_is_estimators;
+use crate::back::rl::monte_carlo::prediction::{monte_carlo_prediction, VisitMode};
+use crate::back::rl::monte_carlo::off_policy::{off_policy_mc_control, off_policy_mc_prediction, BehaviourPolicy, ImportanceSampling};
 use crate::back::rl::monte_carlo::on_policy::{on_policy_first_visit_monte_carlo_control, on_policy_first_visit_monte_carlo_control_dynamic, on_policy_first_visit_monte_carlo_control_secret};
-use crate::back::rl::planning::dyna_q::dyna_q;
+use crate::back::envs::grid_world_env::GridEnv;
+use crate::back::envs::tabular_mdp::TabularMdp;
+use crate::back::envs::rock_paper_scissors::opponents::{BeatsLast, Biased, Copycat, Cyclic, FrequencyCounting, MimicFirstMove, Opponent, Scripted, UniformRandom};
+use crate::back::envs::rock_paper_scissors::RockPaperScissorsEnv;
+use crate::back::rl::planning::count_model::{certainty_equivalence, stochastic_dyna_q};
+use crate::back::rl::planning::dyna_q::{compare_dyna_q_plus, dyna_q, dyna_q_plus, dyna_q_trajectory_sampling};
+use crate::back::rl::planning::mcts::{evaluate_mcts, Mcts, RolloutPolicy};
+use crate::back::rl::planning::prioritized_sweeping::{compare_steps_to_optimal, prioritized_sweeping};
+use crate::back::rl::temporal_difference_learning::double_q_learning::{compare_maximization_bias, double_q_learning, sum_row};
+use crate::back::rl::temporal_difference_learning::eligibility_traces::{sarsa_lambda, td_lambda, watkins_q_lambda, TraceKind};
+use crate::back::rl::temporal_difference_learning::expected_sarsa::expected_sarsa;
+use crate::back::rl::temporal_difference_learning::n_step::{n_step_expected_sarsa, n_step_sarsa, n_step_td_prediction, random_walk_sweep};
 use crate::back::rl::temporal_difference_learning::q_learning::{q_learning, q_learning_dynamic};
+use crate::back::rl::temporal_difference_learning::q_sigma::{compare_n_step_methods, q_sigma, tree_backup, SigmaSchedule};
 use crate::back::rl::temporal_difference_learning::sarsa::{sarsa, sarsa_dynamic, sarsa_secret};
-use crate::cli::elements::{display_pi, display_q, end_of_run, reset_screen, user_choice};
+use crate::back::services::math::{greedy_action, max, sample_action};
+use crate::cli::elements::{display_pi, display_q, display_returns, end_of_run, reset_screen, user_choice};
 
 fn ask_user_for_value(prompt: &str, default: usize) -> usize {
     println!("{}", prompt);
@@ -90,6 +111,29 @@ pub fn testing_env_manually<E: Env>(env: &mut E) {
 
 pub fn test_policy<E: Env>(env: &mut E, policy: Vec<usize>) {
     let mut rng = rand::thread_rng();
+    test_agent(env, |env| {
+        let available_actions: Vec<_> = env.available_actions().iter().cloned().collect();
+        let action = policy[env.state_id()] as i32;
+        if available_actions.contains(&action) {
+            action
+        } else {
+            available_actions[rng.gen_range(0..available_actions.len())]
+        }
+    });
+}
+
+/// Plays the greedy policy of `q` over the actions available in each state
+pub fn test_greedy_policy<E: Env>(env: &mut E, q: &[Vec<f32>]) {
+    test_agent(env, |env| greedy_action(&env.available_actions(), &q[env.state_id()]));
+}
+
+/// Plays one episode with `choose_action` deciding online, e.g. a planner
+pub fn test_agent<E: Env>(env: &mut E, choose_action: impl FnMut(&mut E) -> i32) {
+    test_agent_for(env, usize::MAX, choose_action);
+}
+
+/// `test_agent`, stopping the episode after `max_steps` steps
+pub fn test_agent_for<E: Env>(env: &mut E, max_steps: usize, mut choose_action: impl FnMut(&mut E) -> i32) {
     thread::sleep(Duration::from_millis(500));
     let mut stdout = io::stdout();
     println!("---------------------------------");
@@ -98,9 +142,10 @@ pub fn test_policy<E: Env>(env: &mut E, policy: Vec<usize>) {
 
     env.reset();
     let mut total_score = 0.0;
+    let mut steps = 0;
     thread::sleep(Duration::from_millis(1000));
 
-    while !env.is_game_over() {
+    while !env.is_game_over() && steps < max_steps {
         reset_screen(&mut stdout, "Testing environment...");
 
         env.display();
@@ -110,18 +155,18 @@ pub fn test_policy<E: Env>(env: &mut E, policy: Vec<usize>) {
         println!("Available actions: {:?}", available_actions);
         println!("Enter your action (or type 'quit' to exit): ");
 
-        let state = env.state_id();
-        let mut action = policy[state];
-        if !available_actions.contains(&(action as i32)) {
-            action = available_actions[rng.gen_range(0..available_actions.len())] as usize;
-        }
+        let action = choose_action(env);
         thread::sleep(Duration::from_millis(speed as u64));
-        env.step(action as i32);
+        env.step(action);
+        steps += 1;
 
         total_score += env.score();
     }
     reset_screen(&mut stdout, "Testing environment...");
     println!("-------------------------------------");
+    if !env.is_game_over() {
+        println!("Stopped after {} steps", steps);
+    }
     println!("Game Over! Final Score: {}", total_score);
     env.reset();
 }
@@ -167,8 +212,8 @@ pub fn testing_iterative_policy_evaluation<E: Env>(env: &mut E) {
 }
 
 pub fn testing_policy_iterations<E: Env>(env: &mut E) {
-    let s =  (0..env.num_states()).collect();
-    let a =  (0..env.num_actions()).collect();
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
     let r = env.get_reward_vector();
     let t = env.get_terminal_states();
     let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
@@ -180,17 +225,257 @@ pub fn testing_policy_iterations<E: Env>(env: &mut E) {
     test_policy(env, pi);
 }
 
+pub fn testing_modified_policy_iteration<E: Env>(env: &mut E) {
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let t = env.get_terminal_states();
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let theta = ask_user_for_float("Enter the theta value (default: 0.0001): ", 0.0001);
+    let k = ask_user_for_value("Enter the number of evaluation sweeps per improvement k (default: 3): ", 3);
+
+    let (pi, v, stats) = modified_policy_iteration(&s, &a, &r, &t, env, gamma as f32, theta as f32, k);
+    println!("Optimal Values: {:?}", v);
+    println!("Optimal Policy: {:?}", pi);
+    println!("{} improvements, {} evaluation sweeps", stats.improvements, stats.evaluation_sweeps);
+    test_policy(env, pi);
+}
+
+pub fn testing_q_value_iteration<E: Env>(env: &mut E) {
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let theta = ask_user_for_float("Enter the theta value (default: 0.0001): ", 0.0001);
+
+    let (q, pi) = q_value_iteration(&s, &a, &r, env, gamma as f32, theta as f32);
+    display_q(q);
+    println!("Optimal Policy: {:?}", pi);
+    test_policy(env, pi);
+}
+
+pub fn testing_q_policy_iteration<E: Env>(env: &mut E) {
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let theta = ask_user_for_float("Enter the theta value (default: 0.0001): ", 0.0001);
+
+    let (q, pi) = q_policy_iteration(&s, &a, &r, env, gamma as f32, theta as f32);
+    display_q(q);
+    println!("Optimal Policy: {:?}", pi);
+    test_policy(env, pi);
+}
+
+/// Checks iterative policy evaluation against the exact V^π of a linear solve, on
+/// the uniform random policy and on the greedy policy of value iteration
+pub fn testing_exact_policy_evaluation<E: Env>(env: &mut E) {
+    println!("Exact (linear solve) vs iterative policy evaluation");
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999) as f32;
+    let theta = ask_user_for_float("Enter the theta value (default: 0.0001): ", 0.0001) as f32;
+
+    let uniform = vec![vec![1.0 / a.len() as f32; a.len()]; s.len()];
+    let (_, greedy) = value_iteration(&s, &a, &r, env, gamma, theta);
+    let mut greedy_pi = vec![vec![0.0; a.len()]; s.len()];
+    for (state, &action) in greedy.iter().enumerate() {
+        greedy_pi[state][action] = 1.0;
+    }
+
+    let start = Instant::now();
+    let v_uniform = exact_policy_evaluation(&uniform, &s, &a, &r, env, gamma);
+    let uniform_duration = start.elapsed();
+    let start = Instant::now();
+    let v_greedy = exact_policy_evaluation_deterministic(&greedy, &s, &a, &r, env, gamma);
+    let greedy_duration = start.elapsed();
+
+    for (name, pi, v_exact, exact_duration) in [
+        ("Uniform random policy", &uniform, v_uniform, uniform_duration),
+        ("Value iteration policy", &greedy_pi, v_greedy, greedy_duration),
+    ] {
+        let start = Instant::now();
+        let v_iterative = iterative_policy_evaluation(pi, &s, &a, &r, env, gamma, theta);
+        let iterative_duration = start.elapsed();
+        let max_error = v_exact.iter().zip(v_iterative.iter()).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max);
+
+        println!("{}", name);
+        println!("Exact V: {:?}", v_exact);
+        println!("Iterative V: {:?}", v_iterative);
+        println!("Max |V_iterative - V_exact|: {:.2e} (theta = {:.0e})", max_error, theta);
+        println!("Linear solve in {:?}, iterative evaluation in {:?}", exact_duration, iterative_duration);
+    }
+}
+
+/// Third optimal-value solver: the primal and dual LPs checked against value
+/// iteration and policy iteration
+pub fn testing_linear_programming<E: Env>(env: &mut E) {
+    println!("Linear programming vs value iteration vs policy iteration");
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let t = env.get_terminal_states();
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999) as f32;
+
+    let start = Instant::now();
+    let (v, pi) = linear_programming(&s, &a, &r, env, gamma);
+    let primal_duration = start.elapsed();
+    let start = Instant::now();
+    let dual = linear_programming_dual(&s, &a, &r, env, gamma, &vec![1.0; s.len()]);
+    let dual_duration = start.elapsed();
+    let (v_vi, _) = value_iteration(&s, &a, &r, env, gamma, 1e-6);
+    let (_, v_pi) = policy_iteration(&s, &a, &r, &t, env, gamma, 1e-6);
+    let v_dual = exact_policy_evaluation(&dual.pi, &s, &a, &r, env, gamma);
+    let gap = |other: &[f32]| v.iter().zip(other).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max);
+
+    println!("Optimal Values: {:?}", v);
+    println!("Optimal Policy: {:?}", pi);
+    println!("Occupancy measure x(s, a): {:?}", dual.x);
+    println!("Primal LP in {:?}, dual LP in {:?}", primal_duration, dual_duration);
+    println!("Primal objective Σ V(s): {:.6}, dual objective Σ x(s, a) R(s, a): {:.6}", v.iter().sum::<f32>(), dual.objective);
+    println!("Max |V_LP - V_value_iteration|: {:.2e}", gap(&v_vi));
+    println!("Max |V_LP - V_policy_iteration|: {:.2e}", gap(&v_pi));
+    println!("Max |V_LP - V of the dual policy|: {:.2e}", gap(&v_dual));
+    test_policy(env, pi);
+}
+
+/// Constrained MDP through the dual LP: the discounted number of times one action
+/// is taken from the start state is limited by a budget
+pub fn testing_constrained_mdp<E: Env>(env: &mut E) {
+    println!("Constrained MDP (linear programming)");
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999) as f32;
+    let costly_action = ask_user_for_value("Enter the action with a cost of 1 (default: 1): ", 1).min(a.len() - 1);
+    let budget = ask_user_for_float("Enter the budget on its discounted uses (default: 1.5): ", 1.5) as f32;
+
+    env.reset();
+    let start = env.state_id();
+    let mut mu = vec![0.0; s.len()];
+    mu[start] = 1.0;
+    let mut cost = vec![vec![0.0; a.len()]; s.len()];
+    for cost_s in cost.iter_mut() {
+        cost_s[costly_action] = 1.0;
+    }
+
+    let (v, _) = linear_programming(&s, &a, &r, env, gamma);
+    let Some(solution) = constrained_mdp(&s, &a, &r, env, gamma, &mu, &cost, budget) else {
+        println!("No policy keeps the cost within {}", budget);
+        return;
+    };
+    let expected_cost: f32 = solution.x.iter().flatten().zip(cost.iter().flatten()).map(|(x, c)| x * c).sum();
+    println!("Policy π(a|s): {:?}", solution.pi);
+    println!("V(start): {:.4} (unconstrained: {:.4})", solution.objective, v[start]);
+    println!("Expected discounted cost: {:.4} / {}", expected_cost, budget);
+
+    let mut rng = rand::thread_rng();
+    test_agent(env, |env| sample_action(&solution.pi[env.state_id()], &env.available_actions(), &mut rng));
+}
+
+/// Backward induction over a fixed number of steps, the policy depending on the
+/// step. The test episode ends at the horizon.
+pub fn testing_finite_horizon<E: Env>(env: &mut E) {
+    println!("Finite-horizon backward induction");
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let gamma = ask_user_for_float("Enter the gamma value (default: 1.0): ", 1.0) as f32;
+    let horizon = ask_user_for_value("Enter the horizon (default: 10): ", 10).max(1);
+
+    let (v, pi) = backward_induction(&s, &a, &r, env, gamma, horizon);
+    for t in 0..horizon {
+        println!("t = {}: V_t = {:?}, π_t = {:?}", t, v[t], pi[t]);
+    }
+    env.reset();
+    println!("Expected return from the start over {} steps: {:.4}", horizon, v[0][env.state_id()]);
+
+    let mut t = 0;
+    test_agent_for(env, horizon, |env| {
+        let action = pi[t][env.state_id()] as i32;
+        t += 1;
+        action
+    });
+}
+
+fn ask_backup_scheme() -> BackupScheme {
+    match ask_user_for_value("Backups: 0 synchronous (Jacobi), 1 in-place (Gauss-Seidel), 2 random order, 3 prioritized (default: 1): ", 1) {
+        0 => BackupScheme::Jacobi,
+        2 => BackupScheme::RandomOrder,
+        3 => BackupScheme::Prioritized,
+        _ => BackupScheme::GaussSeidel,
+    }
+}
+
+fn display_value_iteration_report(report: &ValueIterationReport) {
+    let residuals: Vec<String> = report.residuals.iter().map(|residual| format!("{:.1e}", residual)).collect();
+    println!("Residual after each sweep: [{}]", residuals.join(", "));
+    println!("{} sweeps, {} backups in {:?}", report.sweeps, report.backups, report.duration);
+}
+
 pub fn testing_value_iteration<E: Env>(env: &mut E) {
-    let s = (0..env.num_states()).collect();
-    let a = (0..env.num_actions()).collect();
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
     let r = env.get_reward_vector();
     let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
     let theta = ask_user_for_float("Enter the gamma value (default: 0.0001): ", 0.0001);
+    let scheme = ask_backup_scheme();
 
     // Call value iteration
-    let (v, pi) = value_iteration(&s, &a, &r, env, gamma as f32, theta as f32);
+    let (v, pi, report) = value_iteration_with(&s, &a, &r, env, gamma as f32, theta as f32, scheme);
     println!("Optimal Values: {:?}", v);
     println!("Optimal Policy: {:?}", pi);
+    display_value_iteration_report(&report);
+    test_policy(env, pi);
+}
+
+pub fn testing_backup_schemes_comparison<E: Env>(env: &mut E) {
+    println!("Value iteration: synchronous vs in-place vs random order vs prioritized backups");
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.95): ", 0.95);
+    let theta = ask_user_for_float("Enter the theta value (default: 0.0001): ", 0.0001);
+
+    let mut rows = Vec::new();
+    let mut reference: Option<Vec<f32>> = None;
+    for scheme in BACKUP_SCHEMES {
+        let (v, _, report) = value_iteration_with(&s, &a, &r, env, gamma as f32, theta as f32, scheme);
+        println!("{:?}", scheme);
+        display_value_iteration_report(&report);
+        let reference = reference.get_or_insert_with(|| v.clone());
+        let gap = v.iter().zip(reference.iter()).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max);
+        rows.push((scheme, report, gap));
+    }
+    println!("{} states", s.len());
+    println!("{:<14} {:>8} {:>10} {:>14} {:>16}", "Scheme", "Sweeps", "Backups", "Time", "max |V - V_Jacobi|");
+    for (scheme, report, gap) in rows {
+        println!(
+            "{:<14} {:>8} {:>10} {:>14} {:>16.2e}",
+            format!("{:?}", scheme),
+            report.sweeps,
+            report.backups,
+            format!("{:.3?}", report.duration),
+            gap
+        );
+    }
+}
+
+pub fn testing_rtdp<E: Env>(env: &mut E) {
+    println!("Real-Time Dynamic Programming");
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.95): ", 0.95);
+    let theta = ask_user_for_float("Enter the theta value (default: 0.0001): ", 0.0001);
+    let v_init = ask_user_for_float("Enter the optimistic initial value of V (default: 1.0): ", 1.0);
+    let max_episodes = ask_user_for_value("Enter the maximum number of trajectories (default: 10,000): ", 10_000);
+
+    let mdp = TabularMdp::from_env(env);
+    let sampling = rtdp(&mdp, gamma as f32, theta as f32, v_init as f32, max_episodes);
+    let updated = sampling.updated.iter().filter(|&&u| u).count();
+    println!("{} backups, {}/{} states backed up", sampling.backups, updated, mdp.num_states());
+    let pi = sampling.greedy_policy(&mdp, gamma as f32);
+    println!("Values: {:?}", sampling.v);
+    println!("Policy: {:?}", pi);
     test_policy(env, pi);
 }
 
@@ -281,82 +566,305 @@ pub fn testing_monte_carlo_on_policy_secret<E: Env>(env: &mut E) {
     test_policy(env, pi_for_testing);
 }
 
+fn ask_behaviour_policy<E: Env>(env: &E) -> BehaviourPolicy {
+    match ask_user_for_value("Behaviour policy: 0 uniform, 1 epsilon-greedy on Q, 2 fixed random (default: 0): ", 0) {
+        1 => BehaviourPolicy::EpsilonGreedy(ask_user_for_float("Enter the epsilon value (default: 0.3): ", 0.3) as f32),
+        2 => BehaviourPolicy::random(env.num_states(), env.num_actions(), &mut rand::thread_rng()),
+        _ => BehaviourPolicy::Uniform,
+    }
+}
+
+fn ask_importance_sampling() -> ImportanceSampling {
+    match ask_user_for_value("Importance sampling: 0 weighted, 1 ordinary (default: 0): ", 0) {
+        1 => ImportanceSampling::Ordinary,
+        _ => ImportanceSampling::Weighted,
+    }
+}
+
 pub fn testing_monte_carlo_off_policy<E: Env>(env: &mut E) {
     println!("Monte Carlo Off-Policy");
 
     let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
     let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let max_steps = ask_user_for_value("Enter the max number of steps per episode (default: 1,000): ", 1_000);
+    let behaviour = ask_behaviour_policy(env);
+    let sampling = ask_importance_sampling();
 
-    let (pi, q) = off_policy_mc_control_secret(env, num_episodes, gamma as f32);
-    println!("Monte Carlo Off-Policy Control Results:");
+    let (pi, q, ess) = off_policy_mc_control(env, &behaviour, sampling, num_episodes, gamma as f32, max_steps);
+    println!("Monte Carlo Off-Policy Control Results ({:?} importance sampling):", sampling);
     println!("-------------------------------------");
     println!("Policy (pi): {:?}", pi);
     display_q(q);
+    println!("Effective sample size: {:.1} / {} episodes", ess, num_episodes);
     test_policy(env, pi.clone());
 }
 
-pub fn testing_monte_carlo_off_policy_dynamic<E: Env>(env: &mut E) {
+pub fn testing_monte_carlo_off_policy_secret<E: Env>(env: &mut E) {
     println!("Monte Carlo Off-Policy");
 
     let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
     let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
-    let action_spaces = env.get_action_spaces();
-    let (pi, q) = off_policy_mc_control_dynamic(env, num_episodes, gamma as f32, &action_spaces);
-    println!("Monte Carlo Off-Policy Control Results:");
-    println!("-------------------------------------");
-    println!("Policy (pi): {:?}", pi);
+    let max_steps = ask_user_for_value("Enter the max number of steps per episode (default: 1,000): ", 1_000);
+    let behaviour = ask_behaviour_policy(env);
+    let sampling = ask_importance_sampling();
 
-    display_q(q);
+    let (pi, _, ess) = off_policy_mc_control(env, &behaviour, sampling, num_episodes, gamma as f32, max_steps);
+    println!("Monte Carlo Off-Policy Control Results ({:?} importance sampling):", sampling);
+    println!("-------------------------------------");
+    println!("Effective sample size: {:.1} / {} episodes", ess, num_episodes);
     test_policy(env, pi.clone());
 }
 
-pub fn testing_monte_carlo_off_policy_secret<E: Env>(env: &mut E) {
-    println!("Monte Carlo Off-Policy");
+/// Evaluates the value iteration policy from off-policy episodes, against iterative policy evaluation
+pub fn testing_monte_carlo_off_policy_prediction<E: Env>(env: &mut E) {
+    println!("Monte Carlo Off-Policy Prediction (target: value iteration policy)");
 
     let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
     let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
-    let (pi, q) = off_policy_mc_control_secret(env, num_episodes, gamma as f32);
-    println!("Monte Carlo Off-Policy Control Results:");
+    let max_steps = ask_user_for_value("Enter the max number of steps per episode (default: 1,000): ", 1_000);
+    let behaviour = ask_behaviour_policy(env);
+    let sampling = ask_importance_sampling();
+
+    let s: Vec<usize> = (0..env.num_states()).collect();
+    let a: Vec<usize> = (0..env.num_actions()).collect();
+    let r = env.get_reward_vector();
+    let (_, target) = value_iteration(&s, &a, &r, env, gamma as f32, 0.0001);
+    let mut pi = vec![vec![0.0; env.num_actions()]; env.num_states()];
+    for (state, &action) in target.iter().enumerate() {
+        pi[state][action] = 1.0;
+    }
+
+    let (v, q, ess) = off_policy_mc_prediction(env, &pi, &behaviour, sampling, num_episodes, gamma as f32, max_steps);
+    let v_dp = iterative_policy_evaluation(&pi, &s, &a, &r, env, gamma as f32, 0.0001);
+    let max_error = v.iter().zip(v_dp.iter()).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max);
+    println!("Monte Carlo Off-Policy Prediction Results ({:?} importance sampling):", sampling);
     println!("-------------------------------------");
-    test_policy(env, pi.clone());
+    println!("Target policy: {:?}", target);
+    println!("V: {:?}", v);
+    display_q(q);
+    println!("Iterative policy evaluation V: {:?}", v_dp);
+    println!("Max |V_mc - V_dp|: {:.4}", max_error);
+    println!("Effective sample size: {:.1} / {} episodes", ess, num_episodes);
+}
+
+/// Compares the importance sampling estimators on the value of the start state.
+/// The target is the value iteration policy when the env has a model, a policy
+/// learned off-policy otherwise, with a long on-policy run as reference.
+pub fn testing_importance_sampling_comparison<E: Env>(env: &mut E, compare_with_dp: bool) {
+    println!("Monte Carlo Importance Sampling Comparison");
+
+    let num_episodes = ask_user_for_value("Enter the number of episodes per run (default: 100): ", 100);
+    let num_runs = ask_user_for_value("Enter the number of runs (default: 100): ", 100);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.9): ", 0.9) as f32;
+    let max_steps = ask_user_for_value("Enter the max number of steps per episode (default: 1,000): ", 1_000);
+    let behaviour = ask_behaviour_policy(env);
+
+    let num_actions = env.num_actions();
+    let one_hot = |target: &Vec<usize>| {
+        let mut pi = vec![vec![0.0; num_actions]; target.len()];
+        for (state, &action) in target.iter().enumerate() {
+            pi[state][action] = 1.0;
+        }
+        pi
+    };
+    env.reset();
+    let start = env.state_id();
+
+    let (target, pi, truth) = if compare_with_dp {
+        let s: Vec<usize> = (0..env.num_states()).collect();
+        let a: Vec<usize> = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let target = value_iteration(&s, &a, &r, env, gamma, 0.0001).1;
+        let pi = one_hot(&target);
+        let truth = iterative_policy_evaluation(&pi, &s, &a, &r, env, gamma, 0.0001)[start];
+        (target, pi, truth)
+    } else {
+        let target =
+            off_policy_mc_control(env, &BehaviourPolicy::Uniform, ImportanceSampling::Weighted, 10_000, gamma, max_steps).0;
+        let pi = one_hot(&target);
+        let truth = monte_carlo_prediction(env, &pi, 100_000, gamma, VisitMode::FirstVisit, max_steps).0[start];
+        (target, pi, truth)
+    };
+
+    let reports = compare_is_estimators(env, &pi, &behaviour, num_episodes, num_runs, gamma, max_steps);
+    println!("Target policy: {:?}", target);
+    println!("V(start = {}) = {:.4}", start, truth);
+    println!("-------------------------------------");
+    println!(
+        "{:<26} {:>10} {:>10} {:>10} {:>12} {:>8} {:>10}",
+        "Estimator", "Mean", "Variance", "MSE", "Sample var", "ESS", "Max w"
+    );
+    for report in &reports {
+        println!(
+            "{:<26} {:>10.4} {:>10.4} {:>10.4} {:>12.4} {:>8.1} {:>10.1}",
+            format!("{:?}", report.estimator),
+            report.mean,
+            report.variance,
+            report.mse(truth),
+            report.sample_variance,
+            report.mean_ess,
+            report.max_weight
+        );
+    }
 }
 
 pub fn testing_monte_carlo_es<E: Env>(env: &mut E) {
-    println!("Monte Carlo Off-Policy");
+    println!("Monte Carlo Exploring Starts");
 
     let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
     let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let max_steps = ask_user_for_value("Enter the max number of steps per episode (default: 1,000): ", 1_000);
 
-    let (pi, q) = monte_carlo_es(env, num_episodes, gamma as f32);
-    println!("Monte Carlo Off-Policy Control Results:");
+    let (pi, q, returns) = monte_carlo_es(env, num_episodes, gamma as f32, max_steps);
+    println!("Monte Carlo Exploring Starts Results:");
     println!("-------------------------------------");
     println!("Policy (pi): {:?}", pi);
     display_q(q);
+    display_returns(&returns);
     test_policy(env, pi.clone());
 }
 
-pub fn testing_dyna_q<E: Env>(env: &mut E) {
-    println!("Dyna-Q");
+pub fn testing_monte_carlo_prediction<E: Env>(env: &mut E, compare_with_dp: bool) {
+    println!("Monte Carlo Prediction (uniform random policy)");
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let max_steps = ask_user_for_value("Enter the max number of steps per episode (default: 1,000): ", 1_000);
+    let mode = match ask_user_for_value("Enter 0 for first-visit, 1 for every-visit (default: 0): ", 0) {
+        1 => VisitMode::EveryVisit,
+        _ => VisitMode::FirstVisit,
+    };
+    let pi = vec![vec![1.0 / env.num_actions() as f32; env.num_actions()]; env.num_states()];
+
+    let (v, q) = monte_carlo_prediction(env, &pi, num_episodes, gamma as f32, mode, max_steps);
+    println!("Monte Carlo Prediction Results ({:?}):", mode);
+    println!("-------------------------------------");
+    println!("V: {:?}", v);
+    display_q(q);
+
+    if compare_with_dp {
+        let s = (0..env.num_states()).collect();
+        let a = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let v_dp = iterative_policy_evaluation(&pi, &s, &a, &r, env, gamma as f32, 0.0001);
+        let max_error = v.iter().zip(v_dp.iter()).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max);
+        println!("\nIterative policy evaluation V: {:?}", v_dp);
+        println!("Max |V_mc - V_dp|: {:.4}", max_error);
+    }
+}
+
+/// Dyna-Q, or Dyna-Q+ with the exploration bonus when `plus` is set
+pub fn testing_dyna_q<E: Env>(env: &mut E, plus: bool) {
+    println!("{}", if plus { "Dyna-Q+" } else { "Dyna-Q" });
     let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
     let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.01): ", 0.01);
     let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
     let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
-    let planning_steps = 100;
-    let (q, model) = dyna_q(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32, planning_steps);
+    let planning_steps = ask_user_for_value("Enter the number of planning steps (default: 100): ", 100);
+    let (q, model) = if plus {
+        let kappa = ask_user_for_float("Enter the kappa value (default: 0.001): ", 0.001);
+        dyna_q_plus(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32, planning_steps, kappa as f32)
+    } else {
+        dyna_q(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32, planning_steps)
+    };
 
     display_q(q.clone());
     println!("Model : {:?}", model);
-    let pi = q.clone().iter()
-        .map(|state_probs| {
-            state_probs
-                .iter()
-                .enumerate()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-                .map(|(index, _)| index)
-                .unwrap_or(0)
-        })
-        .collect();
+    test_greedy_policy(env, &q);
+}
+
+pub fn testing_dyna_q_trajectory_sampling<E: Env>(env: &mut E) {
+    println!("Dyna-Q (trajectory sampling)");
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.01): ", 0.01);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    let planning_steps = ask_user_for_value("Enter the number of planning steps (default: 100): ", 100);
+    let (q, model) = dyna_q_trajectory_sampling(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32, planning_steps);
+
+    display_q(q.clone());
+    println!("Model : {:?}", model);
+    test_greedy_policy(env, &q);
+}
+
+pub fn testing_prioritized_sweeping<E: Env>(env: &mut E) {
+    println!("Prioritized sweeping");
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.01): ", 0.01);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    let planning_steps = ask_user_for_value("Enter the maximum number of planning steps (default: 100): ", 100);
+    let theta = ask_user_for_float("Enter the priority threshold theta (default: 0.0001): ", 0.0001);
+    let (q, model) = prioritized_sweeping(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32, planning_steps, theta as f32);
+
+    display_q(q.clone());
+    println!("Model : {:?}", model);
+    test_greedy_policy(env, &q);
+}
+
+pub fn testing_mcts<E: Env + Clone>(env: &mut E) {
+    println!("Monte Carlo tree search (UCT)");
+    let budget = ask_user_for_value("Enter the number of simulations per move (default: 1,000): ", 1_000);
+    let c = ask_user_for_float("Enter the exploration constant c (default: 1.4): ", 1.4);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 1.0): ", 1.0);
+    let rollout = match ask_user_for_value("Rollout policy: 1 = uniform, 2 = greedy on a Q-learning table (default: 1): ", 1) {
+        2 => {
+            let num_episodes = ask_user_for_value("Enter the number of Q-learning episodes (default: 10,000): ", 10_000);
+            RolloutPolicy::Greedy(q_learning(env, num_episodes, 0.1, 0.1, gamma as f32))
+        }
+        _ => RolloutPolicy::Uniform,
+    };
+    let mcts = Mcts { budget, c: c as f32, rollout, gamma: gamma as f32, max_depth: 1_000 };
+    // The simulations run on a copy, the real game is only read
+    let mut sim = env.clone();
+
+    let num_games = ask_user_for_value("Enter the number of games to evaluate MCTS on (default: 100): ", 100);
+    let scores = evaluate_mcts(env, &mut sim, &mcts, num_games);
+    println!("Average score over {} games: {:.4}", num_games, scores.iter().sum::<f32>() / num_games as f32);
+
+    let mut rng = rand::thread_rng();
+    test_agent(env, |env| mcts.choose_action(env, &mut sim, &mut rng));
+}
+
+/// Dyna-Q with the count-based model, then value iteration on that model
+fn stochastic_dyna_q_and_certainty_equivalence<E: Env>(env: &mut E, show_q: bool) -> Vec<usize> {
+    println!("Dyna-Q (count-based model) and certainty-equivalence planning");
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.1): ", 0.1);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    let planning_steps = ask_user_for_value("Enter the number of planning steps (default: 10): ", 10);
+    let (q, model) = stochastic_dyna_q(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32, planning_steps);
+    if show_q {
+        display_q(q.clone());
+    }
+
+    let (mut mdp, states) = model.to_mdp();
+    println!("Learned model: {} states seen, rewards {:?}", states.len(), mdp.get_reward_vector());
+    let (v, pi) = certainty_equivalence(&model, env.num_states(), gamma as f32, 0.0001);
+
+    // Compare on the states where the agent tried several actions
+    let mut agree = 0;
+    let mut total = 0;
+    for (i, &s) in states.iter().enumerate() {
+        mdp.set_state(i);
+        let aa = mdp.available_actions();
+        if aa.len() > 1 {
+            total += 1;
+            if greedy_action(&aa, &q[s]) as usize == pi[s] {
+                agree += 1;
+            }
+        }
+    }
+    env.reset();
+    let s0 = env.state_id();
+    println!("Start state: max Q = {:.4}, V of the learned model = {:.4}", max(&q[s0]), v[s0]);
+    println!("Greedy Dyna-Q and certainty-equivalence policies agree on {}/{} states", agree, total);
+    println!("Testing the certainty-equivalence policy");
+    pi
+}
 
+pub fn testing_stochastic_dyna_q<E: Env>(env: &mut E, show_q: bool) {
+    let pi = stochastic_dyna_q_and_certainty_equivalence(env, show_q);
     test_policy(env, pi);
 }
 
@@ -430,7 +938,21 @@ pub fn testing_sarsa_secret<E: Env>(env: &mut E) {
     test_policy(env, pi)
 }
 
-pub fn testing_q_learning<E: Env>(env: &mut E) {
+pub fn testing_expected_sarsa<E: Env>(env: &mut E, show_q: bool) {
+    println!("Expected SARSA");
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.01): ", 0.01);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    let q = expected_sarsa(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32);
+
+    if show_q {
+        display_q(q.clone());
+    }
+    test_greedy_policy(env, &q);
+}
+
+pub fn testing_q_learning<E: Env>(env: &mut E, show_q: bool) {
     println!("Q-Learning");
     let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
     let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.01): ", 0.01);
@@ -438,19 +960,10 @@ pub fn testing_q_learning<E: Env>(env: &mut E) {
     let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
     let q = q_learning(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32);
 
-    display_q(q.clone());
-    let pi = q.clone().iter()
-        .map(|state_probs| {
-            state_probs
-                .iter()
-                .enumerate()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-                .map(|(index, _)| index)
-                .unwrap_or(0)
-        })
-        .collect();
-
-    test_policy(env, pi);
+    if show_q {
+        display_q(q.clone());
+    }
+    test_greedy_policy(env, &q);
 }
 
 pub fn testing_q_learning_dynamic<E: Env>(env: &mut E) {
@@ -477,39 +990,504 @@ pub fn testing_q_learning_dynamic<E: Env>(env: &mut E) {
     test_policy(env, pi);
 }
 
-pub fn testing_q_learning_secret<E: Env>(env: &mut E) {
-    println!("Q-Learning");
+pub fn testing_double_q_learning<E: Env>(env: &mut E, show_q: bool) {
+    println!("Double Q-Learning");
     let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
     let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.01): ", 0.01);
     let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
     let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
-    let q = q_learning(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32);
-    let pi = q.clone().iter()
-        .map(|state_probs| {
-            state_probs
-                .iter()
-                .enumerate()
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-                .map(|(index, _)| index)
-                .unwrap_or(0)
-        })
+    let (q1, q2) = double_q_learning(env, num_episodes, alpha as f32, epsilon as f32, gamma as f32);
+    let q: Vec<Vec<f32>> = (0..q1.len()).map(|s| sum_row(&q1, &q2, s)).collect();
+
+    if show_q {
+        display_q(q.clone());
+    }
+    test_greedy_policy(env, &q);
+}
+
+pub fn testing_n_step_td_prediction<E: Env>(env: &mut E, compare_with_dp: bool) {
+    println!("n-step TD Prediction (uniform random policy)");
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let n = ask_user_for_value("Enter n, the number of steps (default: 4): ", 4);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    let pi = vec![vec![1.0 / env.num_actions() as f32; env.num_actions()]; env.num_states()];
+
+    let v = n_step_td_prediction(env, &pi, n, alpha as f32, gamma as f32, num_episodes);
+    println!("{}-step TD Prediction Results:", n);
+    println!("-------------------------------------");
+    println!("V: {:?}", v);
+
+    if compare_with_dp {
+        let s = (0..env.num_states()).collect();
+        let a = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let v_dp = iterative_policy_evaluation(&pi, &s, &a, &r, env, gamma as f32, 0.0001);
+        let max_error = v.iter().zip(v_dp.iter()).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max);
+        println!("\nIterative policy evaluation V: {:?}", v_dp);
+        println!("Max |V_td - V_dp|: {:.4}", max_error);
+    }
+}
+
+/// n-step SARSA, or n-step Expected SARSA when `expected` is set
+pub fn testing_n_step_sarsa<E: Env>(env: &mut E, expected: bool, show_q: bool) {
+    println!("n-step {}SARSA", if expected { "Expected " } else { "" });
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let n = ask_user_for_value("Enter n, the number of steps (default: 4): ", 4);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.01): ", 0.01);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    let q = if expected {
+        n_step_expected_sarsa(env, n, alpha as f32, epsilon as f32, gamma as f32, num_episodes)
+    } else {
+        n_step_sarsa(env, n, alpha as f32, epsilon as f32, gamma as f32, num_episodes)
+    };
+
+    if show_q {
+        display_q(q.clone());
+    }
+    test_greedy_policy(env, &q);
+}
+
+/// Dutch traces are only offered with `allow_dutch`, for the true online methods
+fn ask_trace_kind(allow_dutch: bool) -> TraceKind {
+    let prompt = if allow_dutch {
+        "Traces: 0 accumulating, 1 replacing, 2 dutch (true online) (default: 1): "
+    } else {
+        "Traces: 0 accumulating, 1 replacing (default: 1): "
+    };
+    match ask_user_for_value(prompt, 1) {
+        0 => TraceKind::Accumulating,
+        2 if allow_dutch => TraceKind::Dutch,
+        _ => TraceKind::Replacing,
+    }
+}
+
+pub fn testing_td_lambda<E: Env>(env: &mut E, compare_with_dp: bool) {
+    println!("TD(λ) Prediction (uniform random policy)");
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let lambda = ask_user_for_float("Enter the lambda value (default: 0.8): ", 0.8);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    let kind = ask_trace_kind(true);
+    let pi = vec![vec![1.0 / env.num_actions() as f32; env.num_actions()]; env.num_states()];
+
+    let v = td_lambda(env, &pi, lambda as f32, alpha as f32, gamma as f32, kind, num_episodes);
+    println!("TD(λ) Prediction Results ({:?} traces):", kind);
+    println!("-------------------------------------");
+    println!("V: {:?}", v);
+
+    if compare_with_dp {
+        let s = (0..env.num_states()).collect();
+        let a = (0..env.num_actions()).collect();
+        let r = env.get_reward_vector();
+        let v_dp = iterative_policy_evaluation(&pi, &s, &a, &r, env, gamma as f32, 0.0001);
+        let max_error = v.iter().zip(v_dp.iter()).map(|(x, y)| (x - y).abs()).fold(0.0f32, f32::max);
+        println!("\nIterative policy evaluation V: {:?}", v_dp);
+        println!("Max |V_td - V_dp|: {:.4}", max_error);
+    }
+}
+
+/// SARSA(λ), or Watkins's Q(λ) when `watkins` is set
+pub fn testing_control_lambda<E: Env>(env: &mut E, watkins: bool, show_q: bool) {
+    println!("{}", if watkins { "Watkins Q(λ)" } else { "SARSA(λ)" });
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let lambda = ask_user_for_float("Enter the lambda value (default: 0.8): ", 0.8);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.01): ", 0.01);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    let kind = ask_trace_kind(!watkins);
+    let q = if watkins {
+        watkins_q_lambda(env, lambda as f32, alpha as f32, epsilon as f32, gamma as f32, kind, num_episodes)
+    } else {
+        sarsa_lambda(env, lambda as f32, alpha as f32, epsilon as f32, gamma as f32, kind, num_episodes)
+    };
+
+    if show_q {
+        display_q(q.clone());
+    }
+    test_greedy_policy(env, &q);
+}
+
+fn ask_sigma_schedule() -> SigmaSchedule {
+    match ask_user_for_value("Sigma: 0 fixed, 1 alternating, 2 random, 3 decaying (default: 0): ", 0) {
+        1 => SigmaSchedule::Alternating,
+        2 => SigmaSchedule::Random(ask_user_for_float("Enter the probability of sigma = 1 (default: 0.5): ", 0.5) as f32),
+        3 => SigmaSchedule::Decaying(ask_user_for_float("Enter the decay factor per episode (default: 0.95): ", 0.95) as f32),
+        _ => SigmaSchedule::Fixed(ask_user_for_float("Enter the sigma value (default: 0.5): ", 0.5) as f32),
+    }
+}
+
+/// n-step Q(σ), or n-step Tree Backup (σ = 0, greedy target) when `tree` is set
+fn n_step_off_policy<E: Env>(env: &mut E, tree: bool) -> Vec<Vec<f32>> {
+    println!("n-step {}", if tree { "Tree Backup" } else { "Q(σ)" });
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 10,000): ", 10_000);
+    let n = ask_user_for_value("Enter n, the number of steps (default: 4): ", 4);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.1): ", 0.1);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.999): ", 0.999);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.01): ", 0.01);
+    if tree {
+        return tree_backup(env, n, alpha as f32, epsilon as f32, gamma as f32, num_episodes).0;
+    }
+    let schedule = ask_sigma_schedule();
+    let target_epsilon = ask_user_for_float("Enter the epsilon of the target policy (default: 0.0): ", 0.0);
+    q_sigma(env, n, schedule, alpha as f32, epsilon as f32, target_epsilon as f32, gamma as f32, num_episodes).0
+}
+
+pub fn testing_n_step_off_policy<E: Env>(env: &mut E, tree: bool, show_q: bool) {
+    let q = n_step_off_policy(env, tree);
+
+    if show_q {
+        display_q(q.clone());
+    }
+    test_greedy_policy(env, &q);
+}
+
+/// Average discounted return of n-step SARSA, Tree Backup and Q(σ) at the start,
+/// over the whole run and at the end of learning
+pub fn testing_n_step_off_policy_comparison<E: Env>(env: &mut E) {
+    println!("n-step SARSA vs Tree Backup vs Q(σ)");
+    let num_runs = ask_user_for_value("Enter the number of runs (default: 20): ", 20).max(1);
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 500): ", 500).max(10);
+    let n = ask_user_for_value("Enter n, the number of steps (default: 4): ", 4);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.1): ", 0.1);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.99): ", 0.99);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.1): ", 0.1);
+
+    let curves = compare_n_step_methods(env, n, alpha as f32, epsilon as f32, gamma as f32, num_episodes, num_runs);
+    let tenth = num_episodes / 10;
+    let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+    println!("Average discounted return per episode ({} runs, n = {})", num_runs, n);
+    println!("{:<28} {:>10} {:>10} {:>10}", "Method", "First 10%", "All", "Last 10%");
+    for (name, curve) in &curves {
+        println!(
+            "{:<28} {:>10.4} {:>10.4} {:>10.4}",
+            name,
+            mean(&curve[..tenth]),
+            mean(curve),
+            mean(&curve[num_episodes - tenth..])
+        );
+    }
+}
+
+/// RMS error of n-step TD on the random walk, for n in 1, 2, 4, ..., 512 and alpha in 0, 0.1, ..., 1
+pub fn testing_random_walk_sweep(num_states: usize) {
+    println!("Random walk with {} states: n-step TD, n vs alpha", num_states);
+    let num_episodes = ask_user_for_value("Enter the number of episodes per run (default: 10): ", 10);
+    let num_runs = ask_user_for_value("Enter the number of runs (default: 100): ", 100);
+    let ns: Vec<usize> = (0..10).map(|i| 1 << i).collect();
+    let alphas: Vec<f32> = (0..=10).map(|i| i as f32 / 10.0).collect();
+
+    let errors = random_walk_sweep(num_states, &ns, &alphas, num_episodes, num_runs);
+    println!("Average RMS error over the {} first episodes", num_episodes);
+    print!("{:>6}", "n");
+    for alpha in &alphas {
+        print!(" {:>6.1}", alpha);
+    }
+    println!();
+    for (n, row) in ns.iter().zip(errors.iter()) {
+        print!("{:>6}", n);
+        for error in row {
+            print!(" {:>6.3}", error);
+        }
+        println!();
+    }
+}
+
+/// Moves as digits 0 (rock), 1 (paper) and 2 (scissors), e.g. "0012"
+fn ask_user_for_moves(prompt: &str, default: &[usize]) -> Vec<usize> {
+    println!("{}", prompt);
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read input");
+
+    let moves: Option<Vec<usize>> = input
+        .trim()
+        .chars()
+        .map(|c| c.to_digit(10).map(|d| d as usize).filter(|&d| d < 3))
         .collect();
+    match moves {
+        Some(moves) if !moves.is_empty() => moves,
+        _ => {
+            println!("Invalid input. Using default value: {:?}", default);
+            default.to_vec()
+        }
+    }
+}
 
-    test_policy(env, pi);
+fn ask_opponent() -> Box<dyn Opponent> {
+    let prompt = "Opponent: 0 mimics the first move, 1 uniform random, 2 biased, 3 copycat, 4 beats last, 5 cyclic, 6 frequency counting, 7 scripted (default: 0): ";
+    match ask_user_for_value(prompt, 0) {
+        1 => Box::new(UniformRandom),
+        2 => {
+            let rock = ask_user_for_float("Enter the weight of rock (default: 0.5): ", 0.5);
+            let paper = ask_user_for_float("Enter the weight of paper (default: 0.25): ", 0.25);
+            let scissors = ask_user_for_float("Enter the weight of scissors (default: 0.25): ", 0.25);
+            let weights = [rock as f32, paper as f32, scissors as f32];
+            if weights.iter().any(|&w| w < 0.0) || weights.iter().sum::<f32>() <= 0.0 {
+                println!("Invalid weights. Using default value: [0.5, 0.25, 0.25]");
+                Box::new(Biased::new([0.5, 0.25, 0.25]))
+            } else {
+                Box::new(Biased::new(weights))
+            }
+        }
+        3 => Box::new(Copycat),
+        4 => Box::new(BeatsLast),
+        5 => Box::new(Cyclic { start: 0 }),
+        6 => Box::new(FrequencyCounting::new()),
+        7 => Box::new(Scripted::new(ask_user_for_moves("Enter the moves, 0 rock, 1 paper, 2 scissors (default: 0012): ", &[0, 0, 1, 2]))),
+        _ => Box::new(MimicFirstMove),
+    }
+}
+
+/// Rock-paper-scissors over a chosen number of rounds against a chosen opponent.
+/// The dynamic programming methods are only offered when the opponent's moves
+/// depend on nothing but the round and the agent's first move.
+pub fn run_rock_paper_scissors() {
+    let rounds = ask_user_for_value("Enter the number of rounds (default: 2): ", 2).max(1);
+    let env = RockPaperScissorsEnv::with(rounds, ask_opponent());
+    if env.has_exact_model() {
+        run_episodic(env);
+    } else {
+        println!("This opponent depends on more than the state, only the model-free methods are available.");
+        thread::sleep(Duration::from_secs(2));
+        run_model_free(env);
+    }
 }
 
-pub fn run<E: Env>(mut env: E) {
+/// Line World with a configurable number of states, for the random walk experiments
+pub fn run_random_walk() {
+    let num_states = ask_user_for_value("Enter the number of non-terminal states (default: 19): ", 19).max(1);
+    let mut stdout = io::stdout();
+    let options = vec![
+        "Sweep : n-step TD, n vs alpha",
+        "Algorithms on this Line World",
+        "Back",
+    ];
+    loop {
+        let selected_index = user_choice(options.clone());
+        reset_screen(&mut stdout, options[selected_index]);
+        match selected_index {
+            0 => testing_random_walk_sweep(num_states),
+            1 => {
+                run(LineEnv::with_size(num_states + 2));
+                continue;
+            }
+            2 => break,
+            _ => {}
+        }
+        end_of_run();
+    }
+}
+
+/// Cumulative reward of Dyna-Q and Dyna-Q+ on a maze whose walls change after `change_after` steps
+pub fn testing_changing_maze_comparison(maze: GridEnv, change_after: usize) {
+    println!("Dyna-Q vs Dyna-Q+, walls changing after {} steps", change_after);
+    let num_steps = ask_user_for_value(&format!("Enter the number of steps (default: {}): ", 3 * change_after), 3 * change_after).max(10);
+    let num_runs = ask_user_for_value("Enter the number of runs (default: 20): ", 20).max(1);
+    let planning_steps = ask_user_for_value("Enter the number of planning steps (default: 10): ", 10);
+    let kappa = ask_user_for_float("Enter the kappa value (default: 0.001): ", 0.001);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.1): ", 0.1);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.95): ", 0.95);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 1.0): ", 1.0);
+
+    let (dyna_q, dyna_q_plus) =
+        compare_dyna_q_plus(&maze, num_steps, num_runs, alpha as f32, epsilon as f32, gamma as f32, planning_steps, kappa as f32);
+    println!("Average cumulative reward ({} runs)", num_runs);
+    println!("{:>8} {:>10} {:>10}", "Step", "Dyna-Q", "Dyna-Q+");
+    for i in 1..=10 {
+        let step = i * num_steps / 10;
+        let marker = if step - num_steps / 10 < change_after && change_after <= step { "  <- walls changed" } else { "" };
+        println!("{:>8} {:>10.2} {:>10.2}{}", step, dyna_q[step - 1], dyna_q_plus[step - 1], marker);
+    }
+}
+
+/// Real steps needed by Dyna-Q and prioritized sweeping to find a shortest path
+/// in the Dyna maze scaled by 1, 2, ... `max_factor`
+pub fn testing_prioritized_sweeping_benchmark() {
+    println!("Dyna-Q vs prioritized sweeping: real steps until the greedy policy is optimal");
+    let max_factor = ask_user_for_value("Enter the largest scale factor of the maze (default: 3): ", 3).max(1);
+    let num_runs = ask_user_for_value("Enter the number of runs (default: 10): ", 10).max(1);
+    let max_steps = ask_user_for_value("Enter the maximum number of real steps per run (default: 50,000): ", 50_000);
+    let planning_steps = ask_user_for_value("Enter the number of planning steps (default: 5): ", 5);
+    let theta = ask_user_for_float("Enter the priority threshold theta (default: 0.0001): ", 0.0001);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.1): ", 0.1);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.95): ", 0.95);
+    // Prioritized sweeping only re-queues the predecessors of an updated pair, so
+    // on these deterministic mazes it relies on full updates
+    let alpha = ask_user_for_float("Enter the alpha value (default: 1.0): ", 1.0);
+
+    // Median of the runs that found the optimal policy, and how many did
+    let summary = |steps: &[Option<usize>]| {
+        let mut reached: Vec<usize> = steps.iter().flatten().copied().collect();
+        reached.sort();
+        match reached.get(reached.len() / 2) {
+            Some(median) => format!("{:>10} {:>3}/{}", median, reached.len(), steps.len()),
+            None => format!("{:>10} {:>3}/{}", "-", 0, steps.len()),
+        }
+    };
+    let mut rows = Vec::new();
+    for factor in 1..=max_factor {
+        let maze = GridEnv::dyna_maze(factor);
+        let (dyna_q, sweeping) =
+            compare_steps_to_optimal(&maze, num_runs, max_steps, alpha as f32, epsilon as f32, gamma as f32, planning_steps, theta as f32);
+        rows.push((maze.num_states(), maze.shortest_path_length().unwrap(), summary(&dyna_q), summary(&sweeping)));
+    }
+    println!("Median real steps until optimal (runs that got there)");
+    println!("{:>8} {:>8} {:>16} {:>16}", "States", "Optimal", "Dyna-Q", "Prior. sweeping");
+    for (num_states, optimal, dyna_q, sweeping) in rows {
+        println!("{:>8} {:>8} {:>16} {:>16}", num_states, optimal, dyna_q, sweeping);
+    }
+}
+
+pub fn testing_backups_to_convergence() {
+    println!("Synchronous value iteration vs RTDP vs trajectory sampling: state backups to convergence");
+    let factor = ask_user_for_value("Enter the scale factor of the maze (default: 2): ", 2).max(1);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 0.95): ", 0.95);
+    let theta = ask_user_for_float("Enter the theta value (default: 0.0001): ", 0.0001);
+    let v_init = ask_user_for_float("Enter the optimistic initial value of V (default: 1.0): ", 1.0);
+    let epsilon = ask_user_for_float("Enter the epsilon of the trajectory sampling (default: 0.1): ", 0.1);
+    let num_runs = ask_user_for_value("Enter the number of runs (default: 10): ", 10).max(1);
+
+    let mdp = TabularMdp::from_env(&mut GridEnv::dyna_maze(factor));
+    let results = compare_backups_to_convergence(&mdp, gamma as f32, theta as f32, v_init as f32, epsilon as f32, num_runs);
+    println!("Dyna maze x{}: {} states", factor, mdp.num_states());
+    println!("{:<32} {:>12} {:>16} {:>14}", "Method", "Backups", "States backed up", "|V - V*|(start)");
+    for (name, backups, updated, error) in results {
+        println!("{:<32} {:>12.0} {:>16.0} {:>14.6}", name, backups, updated, error);
+    }
+}
+
+/// Maze experiments: blocking and shortcut mazes whose walls change during
+/// training, and the Dyna maze at several sizes
+pub fn run_mazes() {
+    let mut stdout = io::stdout();
+    let options = vec![
+        "Blocking maze : Dyna-Q vs Dyna-Q+",
+        "Shortcut maze : Dyna-Q vs Dyna-Q+",
+        "Dyna maze : Dyna-Q vs prioritized sweeping",
+        "Dyna maze : value iteration vs RTDP (backups to convergence)",
+        "Dyna maze : value iteration backup schemes",
+        "Blocking maze : algorithms",
+        "Shortcut maze : algorithms",
+        "Dyna maze : algorithms",
+        "Back",
+    ];
+    loop {
+        let selected_index = user_choice(options.clone());
+        reset_screen(&mut stdout, options[selected_index]);
+        match selected_index {
+            0 => testing_changing_maze_comparison(GridEnv::blocking_maze(1_000), 1_000),
+            1 => testing_changing_maze_comparison(GridEnv::shortcut_maze(3_000), 3_000),
+            2 => testing_prioritized_sweeping_benchmark(),
+            3 => testing_backups_to_convergence(),
+            4 => {
+                let factor = ask_user_for_value("Enter the scale factor of the maze (default: 3): ", 3).max(1);
+                testing_backup_schemes_comparison(&mut GridEnv::dyna_maze(factor));
+            }
+            5 => {
+                run(GridEnv::blocking_maze(1_000));
+                continue;
+            }
+            6 => {
+                run(GridEnv::shortcut_maze(3_000));
+                continue;
+            }
+            7 => {
+                run(GridEnv::dyna_maze(1));
+                continue;
+            }
+            8 => break,
+            _ => {}
+        }
+        end_of_run();
+    }
+}
+
+pub fn testing_maximization_bias_comparison() {
+    println!("Q-Learning vs Double Q-Learning on the maximization bias MDP");
+    let num_runs = ask_user_for_value("Enter the number of runs (default: 1,000): ", 1_000);
+    let num_episodes = ask_user_for_value("Enter the number of episodes (default: 300): ", 300).max(1);
+    let epsilon = ask_user_for_float("Enter the epsilon value (default: 0.1): ", 0.1);
+    let gamma = ask_user_for_float("Enter the gamma value (default: 1.0): ", 1.0);
+    let alpha = ask_user_for_float("Enter the alpha value (default: 0.1): ", 0.1);
+
+    let (left_q, left_double_q) = compare_maximization_bias(num_runs, num_episodes, alpha as f32, epsilon as f32, gamma as f32);
+    println!("% left actions from A (optimal: {:.1}%)", epsilon * 50.0);
+    println!("{:>8} {:>12} {:>18}", "Episode", "Q-Learning", "Double Q-Learning");
+    for episode in [1, 10, 20, 50, 100, 200, 300, 500, 1000] {
+        if episode <= num_episodes {
+            println!("{:>8} {:>11.1}% {:>17.1}%", episode, left_q[episode - 1] * 100.0, left_double_q[episode - 1] * 100.0);
+        }
+    }
+}
+
+pub fn run_maximization_bias<E: Env>(mut env: E) {
+    let mut stdout = io::stdout();
+    let options = vec![
+        "Manuel Test",
+        "Temporal difference: Q-Learning",
+        "Temporal difference: Double Q-Learning",
+        "Comparison : Q-Learning vs Double Q-Learning",
+        "Back",
+    ];
+    loop {
+        let selected_index = user_choice(options.clone());
+        reset_screen(&mut stdout, options[selected_index]);
+        match selected_index {
+            0 => testing_env_manually(&mut env),
+            1 => testing_q_learning(&mut env, true),
+            2 => testing_double_q_learning(&mut env, true),
+            3 => testing_maximization_bias_comparison(),
+            4 => break,
+            _ => {}
+        }
+        end_of_run();
+    }
+}
+
+pub fn run<E: Env + Clone>(mut env: E) {
     let mut selected_index = 0;
     let mut stdout = io::stdout();
     let options = vec![
         "Manuel Test",
         "Dynamic Programming : Policy Iteration",
+        "Dynamic Programming : Modified Policy Iteration",
+        "Dynamic Programming : Q-Value Iteration",
+        "Dynamic Programming : Policy Iteration over Q",
         "Dynamic Programming : Value Iteration",
+        "Dynamic Programming : RTDP",
+        "Dynamic Programming : value iteration backup schemes",
+        "Dynamic Programming : exact vs iterative policy evaluation",
+        "Dynamic Programming : linear programming",
+        "Dynamic Programming : constrained MDP (linear programming)",
+        "Dynamic Programming : finite-horizon backward induction",
+        "Monte Carlo : prediction",
+        "Monte Carlo : exploring starts",
         "Monte Carlo : on policy",
         "Monte Carlo : off policy",
+        "Monte Carlo : off policy prediction",
+        "Monte Carlo : importance sampling comparison",
         "Temporal difference: Q-Learning",
+        "Temporal difference: Double Q-Learning",
         "Temporal difference: SARSA",
+        "Temporal difference: Expected SARSA",
+        "Temporal difference: n-step TD prediction",
+        "Temporal difference: n-step SARSA",
+        "Temporal difference: n-step Expected SARSA",
+        "Temporal difference: n-step Tree Backup",
+        "Temporal difference: n-step Q(σ)",
+        "Comparison : n-step SARSA vs Tree Backup vs Q(σ)",
+        "Eligibility traces: TD(λ) prediction",
+        "Eligibility traces: SARSA(λ)",
+        "Eligibility traces: Watkins Q(λ)",
         "Planning : Dyna-Q",
+        "Planning : Dyna-Q+",
+        "Planning : Dyna-Q (trajectory sampling)",
+        "Planning : Prioritized sweeping",
+        "Planning : Dyna-Q (count-based model)",
+        "Planning : Monte Carlo tree search (UCT)",
         "Back",
     ];
     loop {
@@ -518,28 +1496,76 @@ pub fn run<E: Env>(mut env: E) {
         match selected_index {
             0 => testing_env_manually(&mut env),
             1 => testing_policy_iterations(&mut env),
-            2 => testing_value_iteration(&mut env),
-            3 => testing_monte_carlo_on_policy(&mut env),
-            4 => testing_monte_carlo_off_policy(&mut env),
-            5 => testing_q_learning(&mut env),
-            6 => testing_sarsa(&mut env),
-            7 => testing_dyna_q(&mut env),
-            8 => break,
+            2 => testing_modified_policy_iteration(&mut env),
+            3 => testing_q_value_iteration(&mut env),
+            4 => testing_q_policy_iteration(&mut env),
+            5 => testing_value_iteration(&mut env),
+            6 => testing_rtdp(&mut env),
+            7 => testing_backup_schemes_comparison(&mut env),
+            8 => testing_exact_policy_evaluation(&mut env),
+            9 => testing_linear_programming(&mut env),
+            10 => testing_constrained_mdp(&mut env),
+            11 => testing_finite_horizon(&mut env),
+            12 => testing_monte_carlo_prediction(&mut env, true),
+            13 => testing_monte_carlo_es(&mut env),
+            14 => testing_monte_carlo_on_policy(&mut env),
+            15 => testing_monte_carlo_off_policy(&mut env),
+            16 => testing_monte_carlo_off_policy_prediction(&mut env),
+            17 => testing_importance_sampling_comparison(&mut env, true),
+            18 => testing_q_learning(&mut env, true),
+            19 => testing_double_q_learning(&mut env, true),
+            20 => testing_sarsa(&mut env),
+            21 => testing_expected_sarsa(&mut env, true),
+            22 => testing_n_step_td_prediction(&mut env, true),
+            23 => testing_n_step_sarsa(&mut env, false, true),
+            24 => testing_n_step_sarsa(&mut env, true, true),
+            25 => testing_n_step_off_policy(&mut env, true, true),
+            26 => testing_n_step_off_policy(&mut env, false, true),
+            27 => testing_n_step_off_policy_comparison(&mut env),
+            28 => testing_td_lambda(&mut env, true),
+            29 => testing_control_lambda(&mut env, false, true),
+            30 => testing_control_lambda(&mut env, true, true),
+            31 => testing_dyna_q(&mut env, false),
+            32 => testing_dyna_q(&mut env, true),
+            33 => testing_dyna_q_trajectory_sampling(&mut env),
+            34 => testing_prioritized_sweeping(&mut env),
+            35 => testing_stochastic_dyna_q(&mut env, true),
+            36 => testing_mcts(&mut env),
+            37 => break,
             _ => {}
         }
         end_of_run();
     }
 }
 
-pub fn run_no_dp<E: Env>(mut env: E) {
+/// Short episodic games: policy and value iteration, finite-horizon planning and
+/// the model-free methods
+pub fn run_episodic<E: Env + Clone>(mut env: E) {
     let mut selected_index = 0;
     let mut stdout = io::stdout();
     let options = vec![
         "Manuel Test",
+        "Dynamic Programming : Policy Iteration",
+        "Dynamic Programming : Value Iteration",
+        "Dynamic Programming : finite-horizon backward induction",
+        "Monte Carlo : prediction",
+        "Monte Carlo : exploring starts",
         "Monte Carlo : on policy",
         "Monte Carlo : off policy",
         "Temporal difference: Q-Learning",
+        "Temporal difference: Double Q-Learning",
         "Temporal difference: SARSA",
+        "Temporal difference: Expected SARSA",
+        "Temporal difference: n-step TD prediction",
+        "Temporal difference: n-step SARSA",
+        "Temporal difference: n-step Expected SARSA",
+        "Temporal difference: n-step Tree Backup",
+        "Temporal difference: n-step Q(σ)",
+        "Eligibility traces: TD(λ) prediction",
+        "Eligibility traces: SARSA(λ)",
+        "Eligibility traces: Watkins Q(λ)",
+        "Planning : Dyna-Q (count-based model)",
+        "Planning : Monte Carlo tree search (UCT)",
         "Back"
     ];
     loop {
@@ -547,26 +1573,63 @@ pub fn run_no_dp<E: Env>(mut env: E) {
         reset_screen(&mut stdout, options[selected_index]);
         match selected_index {
             0 => testing_env_manually(&mut env),
-            1 => testing_monte_carlo_on_policy(&mut env),
-            2 => testing_monte_carlo_off_policy(&mut env),
-            3 => testing_q_learning(&mut env),
-            4 => testing_sarsa(&mut env),
-            5 => break,
+            1 => testing_policy_iterations(&mut env),
+            2 => testing_value_iteration(&mut env),
+            3 => testing_finite_horizon(&mut env),
+            4 => testing_monte_carlo_prediction(&mut env, false),
+            5 => testing_monte_carlo_es(&mut env),
+            6 => testing_monte_carlo_on_policy(&mut env),
+            7 => testing_monte_carlo_off_policy(&mut env),
+            8 => testing_q_learning(&mut env, true),
+            9 => testing_double_q_learning(&mut env, true),
+            10 => testing_sarsa(&mut env),
+            11 => testing_expected_sarsa(&mut env, true),
+            12 => testing_n_step_td_prediction(&mut env, false),
+            13 => testing_n_step_sarsa(&mut env, false, true),
+            14 => testing_n_step_sarsa(&mut env, true, true),
+            15 => testing_n_step_off_policy(&mut env, true, true),
+            16 => testing_n_step_off_policy(&mut env, false, true),
+            17 => testing_td_lambda(&mut env, false),
+            18 => testing_control_lambda(&mut env, false, true),
+            19 => testing_control_lambda(&mut env, true, true),
+            20 => testing_stochastic_dyna_q(&mut env, true),
+            21 => testing_mcts(&mut env),
+            22 => break,
             _ => {}
         }
         end_of_run();
     }
 }
 
-pub fn run_no_dp_dynamic<E: Env>(mut env: E) {
+/// `run_episodic` for the games whose action space depends on the step, see
+/// `Env::get_action_spaces`
+pub fn run_episodic_dynamic<E: Env + Clone>(mut env: E) {
     let mut selected_index = 0;
     let mut stdout = io::stdout();
     let options = vec![
         "Manuel Test",
+        "Dynamic Programming : Policy Iteration",
+        "Dynamic Programming : Value Iteration",
+        "Dynamic Programming : finite-horizon backward induction",
+        "Monte Carlo : prediction",
+        "Monte Carlo : exploring starts",
         "Monte Carlo : on policy",
         "Monte Carlo : off policy",
+        "Monte Carlo : importance sampling comparison",
         "Temporal difference: Q-Learning",
+        "Temporal difference: Double Q-Learning",
         "Temporal difference: SARSA",
+        "Temporal difference: Expected SARSA",
+        "Temporal difference: n-step TD prediction",
+        "Temporal difference: n-step SARSA",
+        "Temporal difference: n-step Expected SARSA",
+        "Temporal difference: n-step Tree Backup",
+        "Temporal difference: n-step Q(σ)",
+        "Eligibility traces: TD(λ) prediction",
+        "Eligibility traces: SARSA(λ)",
+        "Eligibility traces: Watkins Q(λ)",
+        "Planning : Dyna-Q (count-based model)",
+        "Planning : Monte Carlo tree search (UCT)",
         "Back",
     ];
     loop {
@@ -574,18 +1637,103 @@ pub fn run_no_dp_dynamic<E: Env>(mut env: E) {
         reset_screen(&mut stdout, options[selected_index]);
         match selected_index {
             0 => testing_env_manually(&mut env),
-            1 => testing_monte_carlo_on_policy_dynamic(&mut env),
-            2 => testing_monte_carlo_off_policy_dynamic(&mut env),
-            3 => testing_q_learning_dynamic(&mut env),
-            4 => testing_sarsa_dynamic(&mut env),
-            5 => break,
+            1 => testing_policy_iterations(&mut env),
+            2 => testing_value_iteration(&mut env),
+            3 => testing_finite_horizon(&mut env),
+            4 => testing_monte_carlo_prediction(&mut env, false),
+            5 => testing_monte_carlo_es(&mut env),
+            6 => testing_monte_carlo_on_policy_dynamic(&mut env),
+            7 => testing_monte_carlo_off_policy(&mut env),
+            8 => testing_importance_sampling_comparison(&mut env, false),
+            9 => testing_q_learning_dynamic(&mut env),
+            10 => testing_double_q_learning(&mut env, true),
+            11 => testing_sarsa_dynamic(&mut env),
+            12 => testing_expected_sarsa(&mut env, true),
+            13 => testing_n_step_td_prediction(&mut env, false),
+            14 => testing_n_step_sarsa(&mut env, false, true),
+            15 => testing_n_step_sarsa(&mut env, true, true),
+            16 => testing_n_step_off_policy(&mut env, true, true),
+            17 => testing_n_step_off_policy(&mut env, false, true),
+            18 => testing_td_lambda(&mut env, false),
+            19 => testing_control_lambda(&mut env, false, true),
+            20 => testing_control_lambda(&mut env, true, true),
+            21 => testing_stochastic_dyna_q(&mut env, true),
+            22 => testing_mcts(&mut env),
+            23 => break,
+            _ => {}
+        }
+        end_of_run();
+    }
+}
+
+/// Model-free methods, planning on learned models and UCT, for the envs without an
+/// exact model but with an exact snapshot, e.g. rock-paper-scissors against an
+/// adaptive opponent
+pub fn run_model_free<E: Env + Clone>(mut env: E) {
+    let mut stdout = io::stdout();
+    let options = vec![
+        "Manuel Test",
+        "Monte Carlo : prediction",
+        "Monte Carlo : exploring starts",
+        "Monte Carlo : on policy",
+        "Monte Carlo : off policy",
+        "Temporal difference: Q-Learning",
+        "Temporal difference: Double Q-Learning",
+        "Temporal difference: SARSA",
+        "Temporal difference: Expected SARSA",
+        "Temporal difference: n-step TD prediction",
+        "Temporal difference: n-step SARSA",
+        "Temporal difference: n-step Expected SARSA",
+        "Temporal difference: n-step Tree Backup",
+        "Temporal difference: n-step Q(σ)",
+        "Eligibility traces: TD(λ) prediction",
+        "Eligibility traces: SARSA(λ)",
+        "Eligibility traces: Watkins Q(λ)",
+        "Planning : Dyna-Q",
+        "Planning : Dyna-Q+",
+        "Planning : Dyna-Q (trajectory sampling)",
+        "Planning : Prioritized sweeping",
+        "Planning : Dyna-Q (count-based model)",
+        "Planning : Monte Carlo tree search (UCT)",
+        "Back",
+    ];
+    loop {
+        let selected_index = user_choice(options.clone());
+        reset_screen(&mut stdout, options[selected_index]);
+        match selected_index {
+            0 => testing_env_manually(&mut env),
+            1 => testing_monte_carlo_prediction(&mut env, false),
+            2 => testing_monte_carlo_es(&mut env),
+            3 => testing_monte_carlo_on_policy(&mut env),
+            4 => testing_monte_carlo_off_policy(&mut env),
+            5 => testing_q_learning(&mut env, true),
+            6 => testing_double_q_learning(&mut env, true),
+            7 => testing_sarsa(&mut env),
+            8 => testing_expected_sarsa(&mut env, true),
+            9 => testing_n_step_td_prediction(&mut env, false),
+            10 => testing_n_step_sarsa(&mut env, false, true),
+            11 => testing_n_step_sarsa(&mut env, true, true),
+            12 => testing_n_step_off_policy(&mut env, true, true),
+            13 => testing_n_step_off_policy(&mut env, false, true),
+            14 => testing_td_lambda(&mut env, false),
+            15 => testing_control_lambda(&mut env, false, true),
+            16 => testing_control_lambda(&mut env, true, true),
+            17 => testing_dyna_q(&mut env, false),
+            18 => testing_dyna_q(&mut env, true),
+            19 => testing_dyna_q_trajectory_sampling(&mut env),
+            20 => testing_prioritized_sweeping(&mut env),
+            21 => testing_stochastic_dyna_q(&mut env, true),
+            22 => testing_mcts(&mut env),
+            23 => break,
             _ => {}
         }
         end_of_run();
     }
 }
 
-pub fn run_no_dp_secret<E: Env>(mut env: E) {
+/// Model-free methods and UCT for the envs known only through their C ABI, whose
+/// copies replay the actions since the last reset (see `SecretEnv::snapshot`)
+pub fn run_no_dp_secret<E: Env + Clone>(mut env: E) {
     let mut selected_index = 0;
     let mut stdout = io::stdout();
     let options = vec![
@@ -593,8 +1741,21 @@ pub fn run_no_dp_secret<E: Env>(mut env: E) {
         "Monte Carlo : on policy",
         "Monte Carlo : off policy",
         "Temporal difference: Q-Learning",
+        "Temporal difference: Double Q-Learning",
         "Temporal difference: SARSA",
+        "Temporal difference: Expected SARSA",
+        "Temporal difference: n-step SARSA",
+        "Temporal difference: n-step Expected SARSA",
+        "Temporal difference: n-step Tree Backup",
+        "Temporal difference: n-step Q(σ)",
+        "Eligibility traces: SARSA(λ)",
+        "Eligibility traces: Watkins Q(λ)",
         "Planning: Dyna-Q",
+        "Planning: Dyna-Q+",
+        "Planning: Dyna-Q (trajectory sampling)",
+        "Planning: Prioritized sweeping",
+        "Planning: Dyna-Q (count-based model)",
+        "Planning: Monte Carlo tree search (UCT)",
         "Back",
     ];
     loop {
@@ -604,10 +1765,23 @@ pub fn run_no_dp_secret<E: Env>(mut env: E) {
             0 => testing_env_manually(&mut env),
             1 => testing_monte_carlo_on_policy_secret(&mut env),
             2 => testing_monte_carlo_off_policy_secret(&mut env),
-            3 => testing_q_learning_secret(&mut env),
-            4 => testing_sarsa_secret(&mut env),
-            5 => testing_dyna_q(&mut env),
-            6 => break,
+            3 => testing_q_learning(&mut env, false),
+            4 => testing_double_q_learning(&mut env, false),
+            5 => testing_sarsa_secret(&mut env),
+            6 => testing_expected_sarsa(&mut env, false),
+            7 => testing_n_step_sarsa(&mut env, false, false),
+            8 => testing_n_step_sarsa(&mut env, true, false),
+            9 => testing_n_step_off_policy(&mut env, true, false),
+            10 => testing_n_step_off_policy(&mut env, false, false),
+            11 => testing_control_lambda(&mut env, false, false),
+            12 => testing_control_lambda(&mut env, true, false),
+            13 => testing_dyna_q(&mut env, false),
+            14 => testing_dyna_q(&mut env, true),
+            15 => testing_dyna_q_trajectory_sampling(&mut env),
+            16 => testing_prioritized_sweeping(&mut env),
+            17 => testing_stochastic_dyna_q(&mut env, false),
+            18 => testing_mcts(&mut env),
+            19 => break,
             _ => {}
         }
         end_of_run();
@@ -1,24 +1,80 @@
 use nalgebra::DVector;
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::seq::SliceRandom;
 
-pub fn argmax(row: &Vec<f32>) -> usize {
-    row.iter()
-        .enumerate()
-        .max_by(|x, y| x.1.partial_cmp(y.1).unwrap())
-        .unwrap()
-        .0
-}
-
-
 pub fn max(row: &Vec<f32>) -> f32 {
     *row.iter()
         .max_by(|a, b| a.partial_cmp(b).unwrap())
         .unwrap()
 }
 
+/// Best action of `q_s` among the available actions `aa`
+pub fn greedy_action(aa: &DVector<i32>, q_s: &[f32]) -> i32 {
+    let mut best_a = aa[0];
+    let mut best_a_score = f32::MIN;
+
+    for &a in aa.iter() {
+        if q_s[a as usize] > best_a_score {
+            best_a = a;
+            best_a_score = q_s[a as usize];
+        }
+    }
+    best_a
+}
+
+/// Best action of `q_s` among the available actions `aa`, ties broken at random
+pub fn greedy_action_random_ties(aa: &DVector<i32>, q_s: &[f32], rng: &mut impl rand::Rng) -> i32 {
+    let best = aa.iter().map(|&a| q_s[a as usize]).fold(f32::MIN, f32::max);
+    let ties: Vec<i32> = aa.iter().copied().filter(|&a| q_s[a as usize] == best).collect();
+    *ties.choose(rng).unwrap()
+}
+
+/// Probability of `a` under `pi_s`, renormalised over the available actions `aa`
+/// (uniform if `pi_s` gives no weight to any of them)
+pub fn policy_probability(pi_s: &[f32], aa: &DVector<i32>, a: i32) -> f32 {
+    let total: f32 = aa.iter().map(|&b| pi_s[b as usize]).sum();
+    if total <= 0.0 {
+        1.0 / aa.len() as f32
+    } else {
+        pi_s[a as usize] / total
+    }
+}
+
+/// Sample an available action from `pi_s`, see `policy_probability`
+pub fn sample_action(pi_s: &[f32], aa: &DVector<i32>, rng: &mut impl rand::Rng) -> i32 {
+    let weights: Vec<f32> = aa.iter().map(|&a| pi_s[a as usize]).collect();
+    match WeightedIndex::new(&weights) {
+        Ok(dist) => aa[dist.sample(rng)],
+        Err(_) => *aa.as_slice().choose(rng).unwrap(),
+    }
+}
+
+/// ε-greedy probabilities over the available actions `aa`, indexed by action id
+pub fn epsilon_greedy_probabilities(aa: &DVector<i32>, q_s: &[f32], epsilon: f32) -> Vec<f32> {
+    let mut row = vec![0.0; q_s.len()];
+    if aa.is_empty() {
+        return row;
+    }
+    for &a in aa.iter() {
+        row[a as usize] = epsilon / aa.len() as f32;
+    }
+    row[greedy_action(aa, q_s) as usize] += 1.0 - epsilon;
+    row
+}
+
+/// Expectation of `q_s` under the ε-greedy policy over the available actions `aa`
+pub fn epsilon_greedy_expectation(aa: &DVector<i32>, q_s: &[f32], epsilon: f32) -> f32 {
+    if aa.is_empty() {
+        return 0.0;
+    }
+    let mean: f32 = aa.iter().map(|&a| q_s[a as usize]).sum::<f32>() / aa.len() as f32;
+    let best = q_s[greedy_action(aa, q_s) as usize];
+    epsilon * mean + (1.0 - epsilon) * best
+}
+
 pub fn epsilon_greedy_action(
     aa: DVector<i32>,          // Available actions
-    q: &Vec<Vec<f32>>,   // Q-table
+    q: &[Vec<f32>],      // Q-table
     state: usize,        // Current state
     epsilon: f32,        // Exploration probability
     rng: &mut impl rand::Rng, // Random number generator
@@ -44,3 +100,29 @@ pub fn epsilon_greedy_action(
         best_a
     }
 }
+
+/// Running mean and variance (Welford's algorithm)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunningStats {
+    pub n: usize,
+    pub mean: f32,
+    m2: f32,
+}
+
+impl RunningStats {
+    pub fn push(&mut self, x: f32) {
+        self.n += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.n as f32;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Unbiased sample variance, 0 with less than two values
+    pub fn variance(&self) -> f32 {
+        if self.n < 2 {
+            0.0
+        } else {
+            self.m2 / (self.n - 1) as f32
+        }
+    }
+}
@@ -1,2 +1,2 @@
 pub mod math;
-pub mod common;
\ No newline at end of file
+pub mod common;
@@ -69,6 +69,18 @@ pub fn display_q(q: Vec<Vec<f32>>) {
     }
 }
 
+pub fn display_returns(returns: &[f32]) {
+    if returns.is_empty() {
+        return;
+    }
+    let window = (returns.len() / 10).max(1);
+    let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+    println!("\nEpisodes: {}", returns.len());
+    println!("Average return: {:.3}", mean(returns));
+    println!("Average return (first {} episodes): {:.3}", window, mean(&returns[..window]));
+    println!("Average return (last {} episodes): {:.3}", window, mean(&returns[returns.len() - window..]));
+}
+
 pub fn user_choice(options: Vec<&str>) -> usize {
     let mut stdout = io::stdout();
     let mut selected_index = 0;
@@ -4,22 +4,43 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode},
 };
 use std::io::Write;
-use crate::cli::elements::user_choice;
+use crate::cli::elements::{end_of_run, user_choice};
 use crate::back::services::common;
 use crate::back::envs;
 
 fn main() {
-    let options = vec![
+    let plugins = unsafe { load_plugins() };
+    let plugin_envs: Vec<(&envs::plugin::Plugin, &String)> = plugins
+        .iter()
+        .flat_map(|plugin| plugin.env_names.iter().map(move |name| (plugin, name)))
+        .collect();
+    let plugin_labels: Vec<String> = plugin_envs
+        .iter()
+        .map(|(plugin, name)| format!("Plugin {} ({})", name, plugin.path))
+        .collect();
+
+    if let Some(name) = requested_env() {
+        run_plugin_env(&plugin_envs, &name);
+        return;
+    }
+
+    let mut options = vec![
         "Line World",
         "Grid World",
-        "Two round Rock Paper Scissors",
+        "Random walk (Line World of any size)",
+        "Rock Paper Scissors",
         "Monty Hall \"paradox\" level 1",
         "Monty Hall \"paradox\" level 2",
+        "Maximization bias",
+        "Mazes (Dyna-Q+, prioritized sweeping)",
         "Secret env 0",
         "Secret env 1",
         "Secret env 2",
-        "Quit"
+        "Our envs through the C ABI",
     ];
+    let first_plugin_index = options.len();
+    options.extend(plugin_labels.iter().map(|label| label.as_str()));
+    options.push("Quit");
 
     let mut selected_index = 0;
 
@@ -31,16 +52,113 @@ fn main() {
         match selected_index {
             0 => { common::run(envs::line_world_env::LineEnv::new()); },
             1 => { common::run(envs::grid_world_env::GridEnv::new()); },
-            2 => { common::run_no_dp(envs::rock_paper_scissors::RockPaperScissorsEnv::new()); }
-            3 => { common::run_no_dp_dynamic(envs::monty_hall_1::MontyHallEnv::new()); }
-            4 => { common::run_no_dp_dynamic(envs::monty_hall_2::MontyHallLevel2Env::new()); }
-            5 => unsafe { common::run_no_dp_secret(envs::secret_env::SecretEnv::new(&format!("secret_env_{}_new", 0))); },
-            6 => unsafe { common::run_no_dp_secret(envs::secret_env::SecretEnv::new(&format!("secret_env_{}_new", 1))); },
-            7 => unsafe { common::run_no_dp_secret(envs::secret_env::SecretEnv::new(&format!("secret_env_{}_new", 2))); },
-            8 => { break; }
-            _ => {}
+            2 => { common::run_random_walk(); }
+            3 => { common::run_rock_paper_scissors(); }
+            4 => { common::run_episodic_dynamic(envs::monty_hall_1::MontyHallEnv::new()); }
+            5 => { common::run_episodic_dynamic(envs::monty_hall_2::MontyHallLevel2Env::new()); }
+            6 => { common::run_maximization_bias(envs::maximization_bias_env::MaximizationBiasEnv::new()); }
+            7 => { common::run_mazes(); }
+            8 => unsafe { common::run_no_dp_secret(envs::secret_env::SecretEnv::new(&format!("secret_env_{}_new", 0))); },
+            9 => unsafe { common::run_no_dp_secret(envs::secret_env::SecretEnv::new(&format!("secret_env_{}_new", 1))); },
+            10 => unsafe { common::run_no_dp_secret(envs::secret_env::SecretEnv::new(&format!("secret_env_{}_new", 2))); },
+            11 => unsafe { run_ffi_env(); },
+            i if i < first_plugin_index + plugin_envs.len() => {
+                let (plugin, name) = plugin_envs[i - first_plugin_index];
+                match unsafe { plugin.create(name) } {
+                    Ok(env) => common::run_no_dp_secret(env),
+                    Err(error) => {
+                        println!("{}", error);
+                        end_of_run();
+                    }
+                }
+            }
+            _ => { break; }
         }
     }
     // Disable raw mode before exiting
     disable_raw_mode().unwrap();
 }
+
+/// Loads the plugins found in `./plugins` and the ones given with `--plugin <path>`.
+/// Rejected plugins are reported once before showing the menu.
+unsafe fn load_plugins() -> Vec<envs::plugin::Plugin> {
+    let mut results = envs::plugin::load_plugins_from_dir("./plugins");
+    let args: Vec<String> = std::env::args().collect();
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--plugin" {
+            match args.get(i + 1) {
+                Some(path) => results.push(envs::plugin::Plugin::load(path)),
+                None => results.push(Err("`--plugin` expects a path to a shared library".to_string())),
+            }
+        }
+    }
+
+    let mut plugins = Vec::new();
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok(plugin) => plugins.push(plugin),
+            Err(error) => errors.push(error),
+        }
+    }
+    if !errors.is_empty() {
+        for error in &errors {
+            println!("{}", error);
+        }
+        end_of_run();
+    }
+    plugins
+}
+
+/// Name given with `--env <name>`, the plugin env to run without showing the menu
+fn requested_env() -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let i = args.iter().position(|arg| arg == "--env")?;
+    match args.get(i + 1) {
+        Some(name) => Some(name.clone()),
+        None => {
+            eprintln!("`--env` expects the name of an env exported by a plugin");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs the plugin env `name`, exiting with an error when no loaded plugin provides it
+fn run_plugin_env(plugin_envs: &[(&envs::plugin::Plugin, &String)], name: &str) {
+    let Some((plugin, _)) = plugin_envs.iter().find(|(_, env_name)| env_name.as_str() == name) else {
+        let known: Vec<&str> = plugin_envs.iter().map(|(_, env_name)| env_name.as_str()).collect();
+        if known.is_empty() {
+            eprintln!("Unknown env `{}`: no plugin is loaded, use `--plugin <path>` or `./plugins`", name);
+        } else {
+            eprintln!("Unknown env `{}`, the loaded plugins provide: {}", name, known.join(", "));
+        }
+        std::process::exit(1);
+    };
+    match unsafe { plugin.create(name) } {
+        Ok(env) => common::run_no_dp_secret(env),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs one of our envs from the `rl_envs` cdylib, going through the same
+/// FFI path as the secret envs.
+unsafe fn run_ffi_env() {
+    let options = vec![
+        "line_world",
+        "grid_world",
+        "rock_paper_scissors",
+        "monty_hall_1",
+        "monty_hall_2",
+        "Back"
+    ];
+    let selected_index = user_choice(options.clone());
+    if selected_index == options.len() - 1 {
+        return;
+    }
+    let path = envs::secret_env::rl_envs_library_path();
+    let env_name = format!("{}_new", options[selected_index]);
+    common::run_no_dp_secret(envs::secret_env::SecretEnv::from_library(path, &env_name));
+}
@@ -0,0 +1,12 @@
+// Shared library exposing our environments through the secret envs C ABI.
+// Only the environments and their FFI wrappers are compiled here, the
+// algorithms and menus stay in the `main` binary.
+#[path = "bin/back/envs/mod.rs"]
+pub mod envs;
+#[path = "bin/back/ffi/mod.rs"]
+pub mod ffi;
+
+// Same paths as in the binary, so `crate::back::envs::...` resolves in both.
+pub mod back {
+    pub use super::{envs, ffi};
+}